@@ -0,0 +1,60 @@
+//! A request guard for routes that only the operators should be able to call.
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Deserialize;
+use std::env;
+
+/// The key admin requests must send. If it is not set, every admin request is refused.
+const ADMIN_API_KEY_VAR: &str = "ADMIN_API_KEY";
+
+/// The header the key is sent in.
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// The body of a request to generate new fields for a base.
+#[derive(Debug, Deserialize)]
+pub struct NewFieldsRequest {
+    pub field_size: u128,
+    pub count: usize,
+    /// Where the caller expects the new fields to start.
+    /// If fields already exist past this point the request is refused, so retrying is safe.
+    pub range_start: Option<u128>,
+}
+
+/// The body of a request to change whether a field is handed out first.
+#[derive(Debug, Deserialize)]
+pub struct PrioritizeRequest {
+    pub prioritize: bool,
+}
+
+/// Proof that the request came with the admin API key.
+pub struct AdminKey;
+
+/// Compare two keys without stopping at the first difference, so the timing doesn't leak the key.
+fn keys_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Ok(expected) = env::var(ADMIN_API_KEY_VAR) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+        match req.headers().get_one(API_KEY_HEADER) {
+            Some(given)
+                if !expected.is_empty() && keys_match(given.as_bytes(), expected.as_bytes()) =>
+            {
+                Outcome::Success(AdminKey)
+            }
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
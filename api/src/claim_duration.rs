@@ -0,0 +1,33 @@
+//! How long a claim holds a field before it can be handed out again.
+
+use chrono::TimeDelta;
+use nice_common::CLAIM_DURATION_HOURS;
+use std::env;
+
+/// Set this to a number of hours to override the default claim duration.
+const CLAIM_DURATION_VAR: &str = "NICE_CLAIM_DURATION_HOURS";
+
+/// The resolved claim duration, managed by Rocket.
+pub struct ClaimDuration(pub TimeDelta);
+
+impl ClaimDuration {
+    /// Read the duration from `NICE_CLAIM_DURATION_HOURS`, defaulting to `CLAIM_DURATION_HOURS`.
+    /// Fractional hours are allowed so claims can be shortened below an hour.
+    pub fn from_env() -> Self {
+        let hours = env::var(CLAIM_DURATION_VAR).ok().map(|v| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|h| h.is_finite() && *h > 0.0)
+                .unwrap_or_else(|| panic!("{CLAIM_DURATION_VAR} must be a positive number"))
+        });
+        let hours = hours.unwrap_or(f64::from(CLAIM_DURATION_HOURS));
+        #[allow(clippy::cast_possible_truncation)]
+        ClaimDuration(TimeDelta::seconds((hours * 3600.0).round() as i64))
+    }
+
+    /// The duration in hours, for logging.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hours(&self) -> f64 {
+        self.0.num_seconds() as f64 / 3600.0
+    }
+}
@@ -0,0 +1,32 @@
+//! The random source used to pick claim parameters, optionally seeded so tests are repeatable.
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::env;
+use std::sync::Mutex;
+
+/// Set this to a number to make claim parameters deterministic.
+const CLAIM_SEED_VAR: &str = "NICE_CLAIM_SEED";
+
+/// Shared claim RNG state, managed by Rocket.
+/// Holds a seeded RNG if `NICE_CLAIM_SEED` is set, otherwise each claim uses the thread RNG.
+pub struct ClaimRng(Option<Mutex<StdRng>>);
+
+impl ClaimRng {
+    /// Seed the RNG from `NICE_CLAIM_SEED` if it is set.
+    pub fn from_env() -> Self {
+        let seed = env::var(CLAIM_SEED_VAR).ok().map(|v| {
+            v.parse::<u64>()
+                .unwrap_or_else(|_| panic!("{CLAIM_SEED_VAR} must be a positive integer"))
+        });
+        ClaimRng(seed.map(|seed| Mutex::new(StdRng::seed_from_u64(seed))))
+    }
+
+    /// Run something with the RNG, holding the lock for the whole call if it is seeded.
+    pub fn with<T>(&self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &self.0 {
+            Some(rng) => f(&mut *rng.lock().unwrap()),
+            None => f(&mut rand::thread_rng()),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! A request guard for finding the IP address of whoever made the request.
+
+use rocket::request::{FromRequest, Outcome, Request};
+use std::convert::Infallible;
+use std::env;
+
+/// Set this to true when running behind a reverse proxy that sets forwarding headers.
+/// Otherwise anyone could spoof their IP by sending the headers themselves.
+const TRUST_PROXY_HEADERS_VAR: &str = "TRUST_PROXY_HEADERS";
+
+/// The resolved IP address of the client, or "unknown" if it could not be determined.
+pub struct ClientIp(pub String);
+
+/// Check whether the forwarding headers should be trusted.
+fn trust_proxy_headers() -> bool {
+    env::var(TRUST_PROXY_HEADERS_VAR).is_ok_and(|v| v == "true" || v == "1")
+}
+
+/// Get the client IP from the forwarding headers.
+/// Takes the last address in X-Forwarded-For since that is the one our proxy added.
+fn get_forwarded_ip(req: &Request<'_>) -> Option<String> {
+    let forwarded_for = req
+        .headers()
+        .get("X-Forwarded-For")
+        .last()
+        .and_then(|h| h.rsplit(',').next())
+        .map(str::trim);
+    let real_ip = req.headers().get_one("X-Real-IP").map(str::trim);
+    forwarded_for
+        .or(real_ip)
+        .filter(|ip| !ip.is_empty())
+        .map(ToString::to_string)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let forwarded_ip = if trust_proxy_headers() {
+            get_forwarded_ip(req)
+        } else {
+            None
+        };
+        // use the peer address directly, since client_ip() trusts X-Real-IP by default
+        let user_ip = forwarded_ip
+            .or_else(|| req.remote().map(|addr| addr.ip().to_string()))
+            .unwrap_or_else(|| "unknown".to_string());
+        Outcome::Success(ClientIp(user_ip))
+    }
+}
@@ -0,0 +1,31 @@
+//! Reject submissions from clients older than a configured minimum version.
+
+use crate::error::{bad_request_error, ApiError};
+use semver::Version;
+use std::env;
+
+/// The env var holding the oldest client version we still accept, like `3.0.0`.
+/// If it is unset, every version is accepted.
+const MIN_CLIENT_VERSION_VAR: &str = "NICE_MIN_CLIENT_VERSION";
+
+/// Check a submitted client version against the configured minimum.
+/// Versions that can't be parsed are treated as too old.
+pub fn check_client_version(client_version: &str) -> Result<(), ApiError> {
+    let Ok(min_version_str) = env::var(MIN_CLIENT_VERSION_VAR) else {
+        return Ok(());
+    };
+    let min_version = match Version::parse(min_version_str.trim()) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Ignoring invalid {MIN_CLIENT_VERSION_VAR} {min_version_str:?}: {e}");
+            return Ok(());
+        }
+    };
+
+    match Version::parse(client_version.trim()) {
+        Ok(version) if version >= min_version => Ok(()),
+        _ => Err(bad_request_error(format!(
+            "Client version {client_version:?} is no longer accepted, please upgrade to version {min_version} or newer."
+        ))),
+    }
+}
@@ -0,0 +1,93 @@
+//! A response fairing that gzips large bodies for clients that accept it.
+//! Stats, leaderboards, and batch claims can get big, but small bodies aren't worth the effort.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+use std::env;
+use std::io::{Cursor, Write};
+
+/// Set this to true to send every response uncompressed, e.g. when debugging with curl.
+const DISABLE_COMPRESSION_VAR: &str = "DISABLE_COMPRESSION";
+
+/// Bodies smaller than this many bytes are sent as-is.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// Compresses response bodies, unless disabled with `DISABLE_COMPRESSION`.
+pub struct GzipFairing {
+    enabled: bool,
+}
+
+impl GzipFairing {
+    pub fn from_env() -> Self {
+        GzipFairing {
+            enabled: !env::var(DISABLE_COMPRESSION_VAR).is_ok_and(|v| v == "true" || v == "1"),
+        }
+    }
+}
+
+/// Check whether the client listed gzip in Accept-Encoding without ruling it out with q=0.
+fn accepts_gzip(req: &Request<'_>) -> bool {
+    req.headers()
+        .get("Accept-Encoding")
+        .flat_map(|h| h.split(','))
+        .any(|encoding| {
+            let mut params = encoding.split(';').map(str::trim);
+            params
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("gzip"))
+                && params.all(|p| !matches!(p, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"))
+        })
+}
+
+/// Compress a body with the default level.
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+#[rocket::async_trait]
+impl Fairing for GzipFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Gzip compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !self.enabled || res.headers().contains("Content-Encoding") || !accepts_gzip(req) {
+            return;
+        }
+        // streamed bodies have no known size, so leave them alone
+        if res
+            .body()
+            .preset_size()
+            .is_none_or(|size| size < MIN_COMPRESSED_SIZE)
+        {
+            return;
+        }
+
+        let body = match res.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(err) => {
+                eprintln!("Failed to read response body for compression: {err}");
+                return;
+            }
+        };
+        match gzip(&body) {
+            Ok(compressed) => {
+                res.set_sized_body(compressed.len(), Cursor::new(compressed));
+                res.set_header(Header::new("Content-Encoding", "gzip"));
+            }
+            Err(err) => {
+                eprintln!("Failed to compress response body: {err}");
+                res.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+        res.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+    }
+}
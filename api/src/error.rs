@@ -0,0 +1,173 @@
+//! Structured error responses for the API.
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+/// Each kind of error the API can respond with.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    UnprocessableEntity,
+    TooManyRequests,
+    InternalError,
+    ServiceUnavailable,
+}
+
+impl ApiErrorKind {
+    /// Get the kind of error for a response status, or None if it was successful.
+    pub fn from_status(status: Status) -> Option<Self> {
+        match status.code {
+            200..=399 => None,
+            400 => Some(ApiErrorKind::BadRequest),
+            401 => Some(ApiErrorKind::Unauthorized),
+            404 => Some(ApiErrorKind::NotFound),
+            409 => Some(ApiErrorKind::Conflict),
+            413 => Some(ApiErrorKind::PayloadTooLarge),
+            422 => Some(ApiErrorKind::UnprocessableEntity),
+            429 => Some(ApiErrorKind::TooManyRequests),
+            503 => Some(ApiErrorKind::ServiceUnavailable),
+            _ => Some(ApiErrorKind::InternalError),
+        }
+    }
+
+    /// The name of this kind of error, as it appears in the JSON body.
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiErrorKind::BadRequest => "bad_request",
+            ApiErrorKind::Unauthorized => "unauthorized",
+            ApiErrorKind::NotFound => "not_found",
+            ApiErrorKind::Conflict => "conflict",
+            ApiErrorKind::PayloadTooLarge => "payload_too_large",
+            ApiErrorKind::UnprocessableEntity => "unprocessable_entity",
+            ApiErrorKind::TooManyRequests => "too_many_requests",
+            ApiErrorKind::InternalError => "internal_error",
+            ApiErrorKind::ServiceUnavailable => "service_unavailable",
+        }
+    }
+
+    /// The HTTP status to send with this kind of error.
+    pub fn status(self) -> Status {
+        match self {
+            ApiErrorKind::BadRequest => Status::BadRequest,
+            ApiErrorKind::Unauthorized => Status::Unauthorized,
+            ApiErrorKind::NotFound => Status::NotFound,
+            ApiErrorKind::Conflict => Status::Conflict,
+            ApiErrorKind::PayloadTooLarge => Status::PayloadTooLarge,
+            ApiErrorKind::UnprocessableEntity => Status::UnprocessableEntity,
+            ApiErrorKind::TooManyRequests => Status::TooManyRequests,
+            ApiErrorKind::InternalError => Status::InternalServerError,
+            ApiErrorKind::ServiceUnavailable => Status::ServiceUnavailable,
+        }
+    }
+}
+
+/// Why a submission failed validation, so clients can react without parsing the message.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SubmitRejection {
+    /// A detailed submission came without a distribution.
+    MissingDistribution,
+    /// The distribution counts don't add up to the size of the field.
+    DistributionSumMismatch,
+    /// The nice numbers don't match the distribution, either for one unique count or in total.
+    NiceCountMismatch { num_uniques: Option<u32> },
+    /// A number was submitted with the wrong unique count.
+    UniqueCountMismatch { number: u128 },
+    /// A number in a near-miss submission isn't close enough to being nice.
+    NotNearMiss { number: u128 },
+    /// The same number was reported more than once.
+    DuplicateNumber { number: u128 },
+    /// A number was reported that isn't part of the claimed field.
+    OutOfRange { number: u128 },
+}
+
+impl SubmitRejection {
+    /// Every reason's name as it appears in the JSON body, in the same order as the variants.
+    pub const NAMES: [&'static str; 7] = [
+        "missing_distribution",
+        "distribution_sum_mismatch",
+        "nice_count_mismatch",
+        "unique_count_mismatch",
+        "not_near_miss",
+        "duplicate_number",
+        "out_of_range",
+    ];
+
+    /// The position of this reason's name in `NAMES`.
+    pub fn index(self) -> usize {
+        match self {
+            SubmitRejection::MissingDistribution => 0,
+            SubmitRejection::DistributionSumMismatch => 1,
+            SubmitRejection::NiceCountMismatch { .. } => 2,
+            SubmitRejection::UniqueCountMismatch { .. } => 3,
+            SubmitRejection::NotNearMiss { .. } => 4,
+            SubmitRejection::DuplicateNumber { .. } => 5,
+            SubmitRejection::OutOfRange { .. } => 6,
+        }
+    }
+}
+
+/// The JSON body sent with an error response.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ApiErrorBody {
+    pub error: ApiErrorKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SubmitRejection>,
+}
+
+/// A full error response, with the status matching the body.
+pub type ApiError = Custom<Json<ApiErrorBody>>;
+
+impl ApiErrorBody {
+    /// Build the full response for an error, with the matching status.
+    pub fn response(kind: ApiErrorKind, message: impl Into<String>) -> ApiError {
+        Custom(
+            kind.status(),
+            Json(ApiErrorBody {
+                error: kind,
+                message: message.into(),
+                reason: None,
+            }),
+        )
+    }
+}
+
+/// The request itself was malformed or is no longer accepted.
+pub fn bad_request_error(message: impl Into<String>) -> ApiError {
+    ApiErrorBody::response(ApiErrorKind::BadRequest, message)
+}
+
+/// The requested resource does not exist.
+pub fn not_found_error(message: impl Into<String>) -> ApiError {
+    ApiErrorBody::response(ApiErrorKind::NotFound, message)
+}
+
+/// The request would clash with data that already exists.
+pub fn conflict_error(message: impl Into<String>) -> ApiError {
+    ApiErrorBody::response(ApiErrorKind::Conflict, message)
+}
+
+/// The submission was well-formed but the data in it failed validation.
+pub fn rejected_submission_error(reason: SubmitRejection, message: impl Into<String>) -> ApiError {
+    let mut error = ApiErrorBody::response(ApiErrorKind::UnprocessableEntity, message);
+    error.1 .0.reason = Some(reason);
+    error
+}
+
+/// Something went wrong on our end, usually with the database.
+pub fn internal_error(message: impl Into<String>) -> ApiError {
+    ApiErrorBody::response(ApiErrorKind::InternalError, message)
+}
+
+/// A service we depend on, like the database, can't be reached right now.
+pub fn service_unavailable_error(message: impl Into<String>) -> ApiError {
+    ApiErrorBody::response(ApiErrorKind::ServiceUnavailable, message)
+}
@@ -6,48 +6,103 @@
 #[macro_use]
 extern crate rocket;
 
-use chrono::{TimeDelta, Utc};
-use nice_common::client_process::get_num_unique_digits;
+use chrono::Utc;
+use dotenvy::dotenv;
 use nice_common::db_util::{
-    get_claim_by_id, get_database_connection, get_field_by_id, insert_claim, insert_submission,
-    try_claim_field, update_field_canon_and_cl,
+    count_all_nice_numbers, get_all_bases, get_canon_submissions_in_base_page, get_database_pool,
+    get_max_field_end_in_base, get_submission_by_claim_id, get_submission_counts_by_user,
+    get_submissions_by_user, insert_new_fields, insert_submission, ping, reassign_fields_to_chunks,
+    reset_field_for_reprocessing, set_field_prioritize, try_claim_and_log_field, try_claim_fields,
+    try_get_base_by_id, PgPool,
 };
-use nice_common::distribution_stats::expand_distribution;
-use nice_common::number_stats::expand_numbers;
+use nice_common::generate_fields::break_range_into_fields;
 use nice_common::{
-    DataToClient, DataToServer, FieldClaimStrategy, NiceNumber, SearchMode, CLAIM_DURATION_HOURS,
-    DEFAULT_FIELD_SIZE, NEAR_MISS_CUTOFF_PERCENT,
+    is_suspiciously_fast, ClaimRecord, DataToClient, DataToServer, FieldClaimStrategy, FieldRecord,
+    SearchMode, SubmissionRecord, UserContribution, DEFAULT_FIELD_SIZE,
 };
-use rand::Rng;
-use rocket::serde::json::{json, Json, Value};
+use rand::{Rng, RngCore};
+use rocket::data::{ByteUnit, Limits};
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::stream::TextStream;
+use rocket::serde::json::{self as serde_json, json, Json, Value};
+use rocket::State;
+use std::env;
+use std::time::Duration;
+
+mod admin;
+mod claim_duration;
+mod claim_rng;
+mod client_ip;
+mod client_version;
+mod compression;
+mod error;
+mod metrics;
+mod openapi;
+mod rate_limit;
+mod stats;
+mod submit_body;
+mod suspicious_rate;
+mod validation;
+use admin::{AdminKey, NewFieldsRequest, PrioritizeRequest};
+use claim_duration::ClaimDuration;
+use claim_rng::ClaimRng;
+use client_ip::ClientIp;
+use compression::GzipFairing;
+use error::{
+    bad_request_error, conflict_error, internal_error, not_found_error, service_unavailable_error,
+    ApiError, ApiErrorBody, ApiErrorKind,
+};
+use metrics::{Metrics, RequestTimingFairing, SubmitOutcome, SubmitOutcomeSlot};
+use rate_limit::{ClaimRateLimit, RateLimiter};
+use stats::{BaseStats, StatsSummary, UserSubmission};
+use submit_body::{submit_limit, SubmitJson, SUBMIT_LIMIT_KEY};
+use suspicious_rate::SuspiciousRate;
+use validation::{matches_saved_submission, validate_submission, ValidatedSubmission};
 
 // TODO: Define error types (4xx, 5xx) and serialize them properly
 // TODO: Log claims, valid submissions, and invalid submissions
 // TODO: update table names to be plural
 
-#[get("/claim/<mode>")]
-fn claim(mode: &str) -> Result<Value, Value> {
-    // get database connection
-    // TODO: database connection pooling
-    let mut conn = get_database_connection();
+/// The most fields a client can claim at once if `MAX_BATCH_CLAIM_SIZE` is not set.
+const DEFAULT_MAX_BATCH_CLAIM_SIZE: usize = 64;
 
-    // set search mode based on path
-    let search_mode = match mode {
-        "detailed" => SearchMode::Detailed,
-        "niceonly" => SearchMode::Niceonly,
-        _ => return Err(not_found()),
-    };
+/// How many users the leaderboard returns if no limit is given.
+const DEFAULT_LEADERBOARD_LIMIT: u32 = 25;
 
-    // get user IP
-    // TODO: actually do this
-    let user_ip = "unknown".to_string();
+/// The most users the leaderboard will return at once.
+const MAX_LEADERBOARD_LIMIT: u32 = 100;
+
+/// How many submissions the user history returns if no limit is given.
+const DEFAULT_USER_SUBMISSIONS_LIMIT: u32 = 50;
 
-    // get rng thread
-    let mut rng = rand::thread_rng();
+/// The most submissions the user history will return at once.
+const MAX_USER_SUBMISSIONS_LIMIT: u32 = 500;
 
+/// The most fields an admin can generate in one request.
+const MAX_GENERATED_FIELDS: usize = 100_000;
+
+/// The most nice numbers we expect in a single submission, with plenty of headroom.
+const MAX_SUBMIT_NUMBERS: u128 = DEFAULT_FIELD_SIZE / 10_000;
+
+/// The largest submission body the API will read. Each nice number takes under 100 bytes.
+/// Other JSON bodies are held to Rocket's default limit.
+const SUBMIT_BODY_LIMIT: u128 = MAX_SUBMIT_NUMBERS * 100;
+
+/// How many submissions the export reads from the database at once.
+const EXPORT_PAGE_SIZE: u32 = 1000;
+
+/// How long the health check waits for a database connection before giving up.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pick the claim strategy and maximum check level for a new claim.
+fn choose_claim_params(search_mode: SearchMode, rng: &mut dyn RngCore) -> (FieldClaimStrategy, u8) {
     let claim_strategy = if rng.gen_range(0..100) < 95 {
         // 95% chance: get lowest valid field
         FieldClaimStrategy::Next
+    } else if search_mode == SearchMode::Detailed {
+        // 5% chance: get random valid field, favoring the ones with the least checks
+        FieldClaimStrategy::WeightedRandom
     } else {
         // 5% chance: get random valid field
         FieldClaimStrategy::Random
@@ -64,49 +119,119 @@ fn claim(mode: &str) -> Result<Value, Value> {
         SearchMode::Niceonly => {
             0 // get CL0 (unchecked), never anything more
         }
+        SearchMode::NearMiss => {
+            1 // get CL0 (unchecked) or CL1 (nice only), since a detailed search already has the near misses
+        }
     };
 
+    (claim_strategy, max_check_level)
+}
+
+/// Build the data sent to the client for a claimed field.
+/// Refuses to hand out a field whose bounds don't agree with its size.
+fn issue_field(
+    field: &FieldRecord,
+    claim: &ClaimRecord,
+    claim_duration: &ClaimDuration,
+) -> Result<DataToClient, ApiError> {
+    let consistent = field.range_start < field.range_end
+        && field.range_end - field.range_start == field.range_size;
+    debug_assert!(consistent, "Malformed field record: {field:?}");
+    if !consistent {
+        println!("ERROR: Refusing to issue malformed field record {field:?}");
+        return Err(internal_error(format!(
+            "Field #{} has inconsistent bounds and could not be issued.",
+            field.field_id
+        )));
+    }
+    Ok(DataToClient {
+        claim_id: claim.claim_id,
+        base: field.base,
+        range_start: field.range_start,
+        range_end: field.range_end,
+        range_size: field.range_size,
+        claim_expires_at: Some(claim.claim_time + claim_duration.0),
+        server_time: Some(Utc::now()),
+    })
+}
+
+#[get("/claim/<mode>?<base>")]
+#[allow(clippy::too_many_arguments)]
+fn claim(
+    mode: &str,
+    base: Option<u32>,
+    client_ip: ClientIp,
+    _rate_limit: ClaimRateLimit,
+    pool: &State<PgPool>,
+    metrics: &State<Metrics>,
+    claim_rng: &State<ClaimRng>,
+    claim_duration: &State<ClaimDuration>,
+) -> Result<Value, ApiError> {
+    // get database connection
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+
+    // set search mode based on path
+    let search_mode = match mode {
+        "detailed" => SearchMode::Detailed,
+        "niceonly" => SearchMode::Niceonly,
+        "nearmiss" => SearchMode::NearMiss,
+        _ => return Err(not_found_error(format!("Unknown search mode {mode}."))),
+    };
+
+    // get user IP
+    let user_ip = client_ip.0;
+
+    // get the claim strategy and max check level
+    let (claim_strategy, max_check_level) =
+        claim_rng.with(|rng| choose_claim_params(search_mode, rng));
+
     // this won't affect anything since all fields will be this size or smaller
     // TODO: implement an "online benchmarking" option for e.g. gh runners that limits this
     let max_range_size = DEFAULT_FIELD_SIZE;
 
     // get the field to search based on claim strategy, max check level, etc
     // try to find a field, respecting previous claims
-    #[allow(clippy::cast_lossless)]
-    let maximum_timestamp = Utc::now() - TimeDelta::hours(CLAIM_DURATION_HOURS as i64);
-    let search_field = if let Some(claimed_field) = try_claim_field(
+    // the field is claimed and the claim is logged in one transaction
+    let maximum_timestamp = Utc::now() - claim_duration.0;
+    let (search_field, claim_record) = if let Some(claimed) = try_claim_and_log_field(
         &mut conn,
         claim_strategy,
         maximum_timestamp,
         max_check_level,
         max_range_size,
-    )? {
-        claimed_field
+        base,
+        search_mode,
+        user_ip.clone(),
+    )
+    .map_err(internal_error)?
+    {
+        claimed
     } else {
         let maximum_timestamp = Utc::now();
         let claim_strategy = FieldClaimStrategy::Random;
-        try_claim_field(
+        try_claim_and_log_field(
             &mut conn,
             claim_strategy,
             maximum_timestamp,
             max_check_level,
             max_range_size,
-        )?.ok_or_else(|| format!("Could not find any field with maximum check level {max_check_level} and maximum size {max_range_size}!"))?
+            base,
+            search_mode,
+            user_ip,
+        )
+        .map_err(internal_error)?
+        .ok_or_else(|| match base {
+            // the client asked for a base we can't serve, don't hand out a different one
+            Some(base) => not_found_error(format!("Could not find any field in base {base} with maximum check level {max_check_level}.")),
+            None => internal_error(format!("Could not find any field with maximum check level {max_check_level} and maximum size {max_range_size}!")),
+        })?
     };
 
-    // log the claim and get the record
-    let claim_record = insert_claim(&mut conn, &search_field, search_mode, user_ip)?;
-
     // build the struct to send to the client
-    let data_for_client = DataToClient {
-        claim_id: claim_record.claim_id,
-        base: search_field.base,
-        range_start: search_field.range_start,
-        range_end: search_field.range_end,
-        range_size: search_field.range_size,
-    };
+    let data_for_client = issue_field(&search_field, &claim_record, claim_duration)?;
 
     // log & return to user
+    metrics.record_claims(claim_record.search_mode, 1);
     println!(
         "New {:?} claim for field #{}",
         claim_record.search_mode, claim_record.field_id
@@ -114,12 +239,97 @@ fn claim(mode: &str) -> Result<Value, Value> {
     Ok(json!(data_for_client))
 }
 
+#[get("/claim/<mode>/batch/<count>?<base>")]
+#[allow(clippy::too_many_arguments)]
+fn claim_batch(
+    mode: &str,
+    count: usize,
+    base: Option<u32>,
+    client_ip: ClientIp,
+    _rate_limit: ClaimRateLimit,
+    pool: &State<PgPool>,
+    metrics: &State<Metrics>,
+    claim_rng: &State<ClaimRng>,
+    claim_duration: &State<ClaimDuration>,
+) -> Result<Json<Vec<DataToClient>>, ApiError> {
+    // get database connection
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+
+    // set search mode based on path
+    let search_mode = match mode {
+        "detailed" => SearchMode::Detailed,
+        "niceonly" => SearchMode::Niceonly,
+        "nearmiss" => SearchMode::NearMiss,
+        _ => return Err(not_found_error(format!("Unknown search mode {mode}."))),
+    };
+
+    // cap the batch at the configured maximum
+    let max_batch_size = env::var("MAX_BATCH_CLAIM_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BATCH_CLAIM_SIZE);
+    let count = count.min(max_batch_size);
+
+    // claim as many fields as are available, respecting previous claims
+    let (claim_strategy, max_check_level) =
+        claim_rng.with(|rng| choose_claim_params(search_mode, rng));
+    let maximum_timestamp = Utc::now() - claim_duration.0;
+    let claimed = try_claim_fields(
+        &mut conn,
+        count,
+        claim_strategy,
+        maximum_timestamp,
+        max_check_level,
+        DEFAULT_FIELD_SIZE,
+        base,
+        search_mode,
+        client_ip.0,
+    )
+    .map_err(internal_error)?;
+
+    // build the structs to send to the client
+    let data_for_client = claimed
+        .iter()
+        .map(|(search_field, claim_record)| issue_field(search_field, claim_record, claim_duration))
+        .collect::<Result<Vec<DataToClient>, ApiError>>()?;
+
+    // log & return to user
+    metrics.record_claims(search_mode, data_for_client.len() as u64);
+    println!(
+        "New {search_mode:?} batch claim for {} fields",
+        data_for_client.len()
+    );
+    Ok(Json(data_for_client))
+}
+
 #[post("/submit", data = "<data>")]
 #[allow(clippy::needless_pass_by_value)]
-fn submit(data: Json<DataToServer>) -> Result<Value, Value> {
+fn submit(
+    data: SubmitJson<DataToServer>,
+    client_ip: ClientIp,
+    pool: &State<PgPool>,
+    suspicious_rate: &State<SuspiciousRate>,
+    outcome: SubmitOutcomeSlot<'_>,
+) -> Result<Value, ApiError> {
+    let result = save_submission(&data, client_ip.0, pool, suspicious_rate.0);
+
+    // leave the outcome for the metrics fairing, which counts it by rejection reason
+    outcome.set(match &result {
+        Ok(saved) => *saved,
+        Err(err) => SubmitOutcome::Rejected(err.1 .0.reason),
+    });
+    result.map(|_| "OK".into())
+}
+
+/// Validate and save a submission, or recognize it as a repeat of one already saved.
+fn save_submission(
+    data: &DataToServer,
+    user_ip: String,
+    pool: &PgPool,
+    suspicious_rate: f64,
+) -> Result<SubmitOutcome, ApiError> {
     // get database connection
-    // TODO: database connection pooling
-    let mut conn = get_database_connection();
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
 
     // get submission data out of json container
     let submit_data = DataToServer {
@@ -130,141 +340,333 @@ fn submit(data: Json<DataToServer>) -> Result<Value, Value> {
         nice_numbers: data.nice_numbers.clone(),
     };
 
-    // get user IP
-    // TODO: actually do this
-    let user_ip = "unknown".to_string();
+    // a client retrying after a dropped response has already been counted, don't count it again
+    if let Some(existing) =
+        get_submission_by_claim_id(&mut conn, submit_data.claim_id).map_err(internal_error)?
+    {
+        return repeat_submission(&existing, &submit_data);
+    }
 
-    // get claim record
-    let claim_record = get_claim_by_id(&mut conn, submit_data.claim_id)?;
+    // run every check before saving anything
+    let ValidatedSubmission {
+        claim_record,
+        field_record,
+        distribution,
+        numbers,
+    } = validate_submission(&mut conn, &submit_data)?;
+
+    // flag fields searched faster than any honest client could manage, they are kept but not trusted
+    #[allow(clippy::cast_precision_loss)]
+    let elapsed_secs = (Utc::now() - claim_record.claim_time).num_milliseconds() as f32 / 1000f32;
+    let suspicious = is_suspiciously_fast(field_record.range_size, elapsed_secs, suspicious_rate);
+    if suspicious {
+        println!(
+            "Suspicious submission for field #{}: {} numbers in {elapsed_secs} seconds",
+            field_record.field_id, field_record.range_size
+        );
+    }
 
-    // get field record (for base)
-    let field_record = get_field_by_id(&mut conn, claim_record.field_id)?;
-    let base = field_record.base;
+    // detailed searches are checked by the server, the others are on the honor system
+    // every nice number is also a near miss, so those count as a nice-only search
+    let check_level = match claim_record.search_mode {
+        SearchMode::Detailed => 2,
+        SearchMode::Niceonly | SearchMode::NearMiss => 1,
+    };
 
-    // expand nice numbers
-    let numbers_expanded = expand_numbers(&submit_data.nice_numbers, base);
+    // save it, raising the check level in the same transaction
+    let inserted = insert_submission(
+        &mut conn,
+        claim_record.clone(),
+        submit_data.clone(),
+        user_ip,
+        suspicious,
+        distribution,
+        numbers,
+        (!suspicious).then_some(check_level),
+    )
+    .map_err(internal_error)?;
+
+    // another request for the same claim was saved while we were validating this one
+    if inserted.is_none() {
+        let existing = get_submission_by_claim_id(&mut conn, submit_data.claim_id)
+            .map_err(internal_error)?
+            .ok_or_else(|| internal_error("Submission conflicted but could not be found."))?;
+        return repeat_submission(&existing, &submit_data);
+    }
 
-    match claim_record.search_mode {
-        SearchMode::Niceonly => {
-            // no checks, honor system
-            insert_submission(
-                &mut conn,
-                claim_record.clone(),
-                submit_data,
-                user_ip,
-                None,
-                numbers_expanded,
-            )?;
-            // set CL to 1 if it's 0
-            if field_record.check_level == 0 {
-                update_field_canon_and_cl(
-                    &mut conn,
-                    field_record.field_id,
-                    field_record.canon_submission_id,
-                    1,
-                )?;
-            }
-        }
-        SearchMode::Detailed => {
-            // run through some basic validity tests
-            match &submit_data.unique_distribution {
-                Some(distribution) => {
-                    // expand distribution
-                    let distribution_expanded = expand_distribution(distribution, base);
-
-                    // check distribution count sums to range_size
-                    let dist_total_count = distribution.iter().fold(0, |acc, d| acc + d.count);
-                    if dist_total_count != field_record.range_size {
-                        return Err(format!(
-                            "Total distribution count is incorrect (submitted {}, range was {}).",
-                            dist_total_count, field_record.range_size
-                        )
-                        .into());
-                    }
-
-                    // get the near-miss cutoff
-                    #[allow(
-                        clippy::cast_sign_loss,
-                        clippy::cast_possible_truncation,
-                        clippy::cast_precision_loss
-                    )]
-                    let num_uniques_cutoff =
-                        (base as f32 * NEAR_MISS_CUTOFF_PERCENT).floor() as u32;
-
-                    // check count of nice numbers against distribution
-                    for d in &distribution_expanded {
-                        if d.num_uniques > num_uniques_cutoff {
-                            let count_numbers = numbers_expanded
-                                .iter()
-                                .filter(|n| n.num_uniques == d.num_uniques)
-                                .collect::<Vec<&NiceNumber>>()
-                                .len();
-                            if count_numbers as u128 != d.count {
-                                return Err(format!(
-                                    "Count of nice numbers with {} uniques does not match distribution (submitted {}, distribution claimed {}).",
-                                    d.num_uniques, count_numbers, d.count
-                                )
-                                .into());
-                            }
-                        }
-                    }
-
-                    // check total number of nice numbers
-                    let num_total_count = numbers_expanded.len();
-                    let dist_total_count_above_cutoff = distribution
-                        .iter()
-                        .filter(|d| d.num_uniques > num_uniques_cutoff)
-                        .fold(0, |acc, d| acc + d.count);
-                    if num_total_count as u128 != dist_total_count_above_cutoff {
-                        return Err(format!(
-                            "Count of nice numbers does not match distribution (submitted {num_total_count}, distribution claimed {dist_total_count_above_cutoff})."
-                        )
-                        .into());
-                    }
-
-                    // check each nice number provided
-                    for n in &numbers_expanded {
-                        let calculated_num_uniques = get_num_unique_digits(n.number, base);
-                        if calculated_num_uniques != n.num_uniques {
-                            return Err(format!(
-                                "Unique count for {} is incorrect (submitted as {}, sever calculated {}).", n.number, n.num_uniques, calculated_num_uniques
-                            ).into());
-                        }
-                    }
-
-                    // save it
-                    insert_submission(
-                        &mut conn,
-                        claim_record.clone(),
-                        submit_data,
-                        user_ip,
-                        Some(distribution_expanded),
-                        numbers_expanded,
-                    )?;
-                    if field_record.check_level < 2 {
-                        update_field_canon_and_cl(
-                            &mut conn,
-                            field_record.field_id,
-                            field_record.canon_submission_id,
-                            2,
-                        )?;
-                    }
+    // log & respond to user
+    println!(
+        "New {:?} submission for field #{}",
+        claim_record.search_mode, claim_record.field_id
+    );
+    Ok(SubmitOutcome::Saved)
+}
+
+/// Respond to a claim that was already submitted.
+/// A retry with the same results is accepted without saving it again, different results are rejected.
+fn repeat_submission(
+    existing: &SubmissionRecord,
+    submit_data: &DataToServer,
+) -> Result<SubmitOutcome, ApiError> {
+    if !matches_saved_submission(existing, submit_data) {
+        return Err(conflict_error(format!(
+            "Claim #{} was already submitted with different results.",
+            existing.claim_id
+        )));
+    }
+    println!(
+        "Repeat submission for claim #{}, already saved as submission #{}",
+        existing.claim_id, existing.submission_id
+    );
+    Ok(SubmitOutcome::Repeat)
+}
+
+#[post("/submit/validate", data = "<data>")]
+#[allow(clippy::needless_pass_by_value)]
+fn submit_validate(
+    data: SubmitJson<DataToServer>,
+    pool: &State<PgPool>,
+) -> Result<Value, ApiError> {
+    // get database connection
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+
+    // run the same checks as a real submission, but never save it
+    let validated = validate_submission(&mut conn, &data)?;
+
+    // log & respond to user
+    println!(
+        "Validated {:?} submission for field #{}",
+        validated.claim_record.search_mode, validated.claim_record.field_id
+    );
+    Ok("OK".into())
+}
+
+#[get("/stats")]
+fn stats_all(pool: &State<PgPool>) -> Result<Json<StatsSummary>, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    let bases = get_all_bases(&mut conn).map_err(internal_error)?;
+    let total_nice_numbers = count_all_nice_numbers(&mut conn).map_err(internal_error)?;
+    Ok(Json(StatsSummary {
+        total_nice_numbers,
+        bases: bases.iter().map(BaseStats::from).collect(),
+    }))
+}
+
+#[get("/stats/<base>")]
+fn stats_base(base: u32, pool: &State<PgPool>) -> Result<Json<BaseStats>, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    match try_get_base_by_id(&mut conn, base).map_err(internal_error)? {
+        Some(base_record) => Ok(Json(BaseStats::from(&base_record))),
+        None => Err(not_found_error(format!(
+            "Base {base} is not part of the search."
+        ))),
+    }
+}
+
+#[get("/leaderboard?<limit>&<offset>")]
+fn leaderboard(
+    limit: Option<u32>,
+    offset: Option<u32>,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<UserContribution>>, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    let limit = limit
+        .unwrap_or(DEFAULT_LEADERBOARD_LIMIT)
+        .min(MAX_LEADERBOARD_LIMIT);
+    let users = get_submission_counts_by_user(&mut conn, limit, offset.unwrap_or(0))
+        .map_err(internal_error)?;
+    Ok(Json(users))
+}
+
+#[get("/user/<username>/submissions?<limit>")]
+fn user_submissions(
+    username: &str,
+    limit: Option<u32>,
+    pool: &State<PgPool>,
+) -> Result<Json<Vec<UserSubmission>>, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    let limit = limit
+        .unwrap_or(DEFAULT_USER_SUBMISSIONS_LIMIT)
+        .min(MAX_USER_SUBMISSIONS_LIMIT);
+    let submissions =
+        get_submissions_by_user(&mut conn, username, limit).map_err(internal_error)?;
+    Ok(Json(submissions.iter().map(UserSubmission::from).collect()))
+}
+
+#[get("/export/<base>")]
+fn export_base(
+    base: u32,
+    pool: &State<PgPool>,
+) -> Result<(ContentType, TextStream![String]), ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    if try_get_base_by_id(&mut conn, base)
+        .map_err(internal_error)?
+        .is_none()
+    {
+        return Err(not_found_error(format!("Base {base} does not exist.")));
+    }
+    drop(conn);
+
+    // page through the submissions so only one page is ever held in memory
+    let pool = pool.inner().clone();
+    let stream = TextStream! {
+        let mut after_id = None;
+        loop {
+            let pool = pool.clone();
+            let page = rocket::tokio::task::spawn_blocking(move || {
+                let mut conn = pool.get().map_err(|err| err.to_string())?;
+                get_canon_submissions_in_base_page(&mut conn, base, after_id, EXPORT_PAGE_SIZE)
+            })
+            .await;
+            let page = match page.map_err(|err| err.to_string()).and_then(|page| page) {
+                Ok(page) => page,
+                Err(err) => {
+                    eprintln!("Export of base {base} failed after submission {after_id:?}: {err}");
+                    break;
                 }
-                None => {
-                    return Err(json!(
-                        "Unique distribution must be present for detailed searches."
-                    ))
+            };
+            let Some(last) = page.last() else {
+                break;
+            };
+            after_id = Some(last.submission_id);
+            let is_last_page = page.len() < EXPORT_PAGE_SIZE as usize;
+            for submission in page {
+                match serde_json::to_string(&submission) {
+                    Ok(line) => yield line + "\n",
+                    Err(err) => eprintln!("Failed to serialize submission #{}: {err}", submission.submission_id),
                 }
             }
+            if is_last_page {
+                break;
+            }
         }
+    };
+    Ok((ContentType::new("application", "x-ndjson"), stream))
+}
+
+#[get("/health")]
+fn health(pool: &State<PgPool>) -> Result<Value, ApiError> {
+    let mut conn = pool
+        .get_timeout(HEALTH_CHECK_TIMEOUT)
+        .map_err(|err| service_unavailable_error(format!("Database unreachable: {err}")))?;
+    ping(&mut conn)
+        .map_err(|err| service_unavailable_error(format!("Database unreachable: {err}")))?;
+    Ok(json!({"status": "ok"}))
+}
+
+#[get("/metrics")]
+fn prometheus_metrics(metrics: &State<Metrics>) -> String {
+    metrics.render()
+}
+
+#[get("/openapi.json")]
+fn openapi_spec() -> Value {
+    openapi::spec()
+}
+
+#[post("/admin/base/<base>/fields", data = "<request>")]
+#[allow(clippy::needless_pass_by_value)]
+fn admin_generate_fields(
+    base: u32,
+    request: Json<NewFieldsRequest>,
+    _admin: AdminKey,
+    pool: &State<PgPool>,
+) -> Result<Value, ApiError> {
+    if request.field_size == 0 || request.count == 0 || request.count > MAX_GENERATED_FIELDS {
+        return Err(bad_request_error(format!(
+            "Field size must be positive and count must be between 1 and {MAX_GENERATED_FIELDS}."
+        )));
     }
 
-    // log & respond to user
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+
+    let Some(base_record) = try_get_base_by_id(&mut conn, base).map_err(internal_error)? else {
+        return Err(not_found_error(format!("Base {base} does not exist.")));
+    };
+
+    // pick up where the last field left off
+    let next_start = get_max_field_end_in_base(&mut conn, base)
+        .map_err(internal_error)?
+        .unwrap_or(base_record.range_start);
+    if request.range_start.is_some_and(|start| start != next_start) {
+        return Err(conflict_error(format!(
+            "Fields in base {base} already extend to {next_start}."
+        )));
+    }
+    if next_start >= base_record.range_end {
+        return Err(conflict_error(format!(
+            "Base {base} already has fields covering its entire range."
+        )));
+    }
+
+    let range_end = request
+        .field_size
+        .saturating_mul(request.count as u128)
+        .saturating_add(next_start)
+        .min(base_record.range_end);
+    let new_fields = break_range_into_fields(next_start, range_end, request.field_size);
+    let num_created = new_fields.len();
+    insert_new_fields(&mut conn, base, new_fields).map_err(internal_error)?;
+    reassign_fields_to_chunks(&mut conn, base).map_err(internal_error)?;
+
+    Ok(json!({
+        "base": base,
+        "fields_created": num_created,
+        "range_start": next_start,
+        "range_end": range_end,
+    }))
+}
+
+#[post("/admin/field/<field_id>/prioritize", data = "<request>")]
+#[allow(clippy::needless_pass_by_value)]
+fn admin_prioritize_field(
+    field_id: u128,
+    request: Json<PrioritizeRequest>,
+    _admin: AdminKey,
+    pool: &State<PgPool>,
+) -> Result<Value, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    let Some(field) =
+        set_field_prioritize(&mut conn, field_id, request.prioritize).map_err(internal_error)?
+    else {
+        return Err(not_found_error(format!(
+            "Field #{field_id} does not exist."
+        )));
+    };
+
     println!(
-        "New {:?} submission for field #{}",
-        claim_record.search_mode, claim_record.field_id
+        "Set prioritize to {} for field #{field_id}",
+        field.prioritize
     );
-    Ok("OK".into())
+    Ok(json!({
+        "field_id": field.field_id,
+        "base": field.base,
+        "prioritize": field.prioritize,
+    }))
+}
+
+#[post("/admin/field/<field_id>/reset")]
+fn admin_reset_field(
+    field_id: u128,
+    _admin: AdminKey,
+    pool: &State<PgPool>,
+) -> Result<Value, ApiError> {
+    let mut conn = pool.get().map_err(|err| internal_error(err.to_string()))?;
+    let Some(num_disqualified) =
+        reset_field_for_reprocessing(&mut conn, field_id).map_err(internal_error)?
+    else {
+        return Err(not_found_error(format!(
+            "Field #{field_id} does not exist."
+        )));
+    };
+
+    println!(
+        "Reset field #{field_id} for reprocessing, disqualified {num_disqualified} submissions"
+    );
+    Ok(json!({
+        "field_id": field_id,
+        "submissions_disqualified": num_disqualified,
+    }))
 }
 
 #[get("/")]
@@ -274,12 +676,93 @@ fn index() -> Value {
 
 #[catch(404)]
 fn not_found() -> Value {
-    "The requested resource could not be found. Available resources include /claim/detailed, /claim/niceonly, and /submit. Visit https://nicenumbers.net for more information.".into()
+    "The requested resource could not be found. Available resources include /claim/detailed, /claim/niceonly, /claim/nearmiss, /claim/<mode>/batch/<count>, /submit, /stats, /leaderboard, /user/<username>/submissions, /export/<base>, and /openapi.json. Visit https://nicenumbers.net for more information.".into()
+}
+
+#[catch(401)]
+fn unauthorized() -> ApiError {
+    ApiErrorBody::response(
+        ApiErrorKind::Unauthorized,
+        "This resource requires a valid API key in the X-Api-Key header.",
+    )
+}
+
+#[catch(413)]
+fn payload_too_large(req: &Request) -> ApiError {
+    let limit = if req.uri().path().starts_with("/submit") {
+        submit_limit(req)
+    } else {
+        req.limits().get("json").unwrap_or(Limits::JSON).as_u64()
+    };
+    ApiErrorBody::response(
+        ApiErrorKind::PayloadTooLarge,
+        format!("The request body is larger than the limit of {limit} bytes."),
+    )
+}
+
+#[catch(429)]
+fn too_many_requests() -> ApiError {
+    ApiErrorBody::response(
+        ApiErrorKind::TooManyRequests,
+        "Too many claims from this IP, please slow down and try again later.",
+    )
 }
 
 #[launch]
 fn rocket() -> _ {
-    rocket::build()
-        .mount("/", routes![claim, submit, index])
-        .register("/", catchers![not_found])
+    dotenv().ok();
+    let pool = get_database_pool();
+    let pool_state = pool.state();
+    println!(
+        "Database pool ready with a maximum of {} connections ({} idle).",
+        pool.max_size(),
+        pool_state.idle_connections
+    );
+    let claim_duration = ClaimDuration::from_env();
+    println!("Claims expire after {} hours.", claim_duration.hours());
+    let suspicious_rate = SuspiciousRate::from_env();
+    println!(
+        "Submissions faster than {} numbers per second are flagged as suspicious.",
+        suspicious_rate.0
+    );
+    let limits = Limits::default().limit(SUBMIT_LIMIT_KEY, ByteUnit::from(SUBMIT_BODY_LIMIT));
+    rocket::custom(rocket::Config::figment().merge(("limits", limits)))
+        .manage(pool)
+        .manage(RateLimiter::from_env())
+        .manage(ClaimRng::from_env())
+        .manage(claim_duration)
+        .manage(suspicious_rate)
+        .manage(Metrics::default())
+        .attach(RequestTimingFairing)
+        .attach(GzipFairing::from_env())
+        .mount(
+            "/",
+            routes![
+                claim,
+                claim_batch,
+                submit,
+                submit_validate,
+                stats_all,
+                stats_base,
+                leaderboard,
+                user_submissions,
+                export_base,
+                health,
+                prometheus_metrics,
+                openapi_spec,
+                admin_generate_fields,
+                admin_prioritize_field,
+                admin_reset_field,
+                index
+            ],
+        )
+        .register(
+            "/",
+            catchers![
+                not_found,
+                unauthorized,
+                payload_too_large,
+                too_many_requests
+            ],
+        )
 }
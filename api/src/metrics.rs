@@ -0,0 +1,250 @@
+//! Counters and timings for the API, served in the Prometheus text format.
+//! Everything is kept in atomics so updating and scraping never block a request.
+
+use crate::error::{ApiErrorKind, SubmitRejection};
+use nice_common::SearchMode;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Every search mode that can be claimed, in the order their counters are stored.
+const SEARCH_MODES: [SearchMode; 3] = [
+    SearchMode::Detailed,
+    SearchMode::Niceonly,
+    SearchMode::NearMiss,
+];
+
+/// Every kind of error a submission can be rejected with, in the order their counters are stored.
+/// Rejections that come with a `SubmitRejection` are counted by that reason instead.
+const REJECTION_KINDS: [ApiErrorKind; 9] = [
+    ApiErrorKind::BadRequest,
+    ApiErrorKind::Unauthorized,
+    ApiErrorKind::NotFound,
+    ApiErrorKind::Conflict,
+    ApiErrorKind::PayloadTooLarge,
+    ApiErrorKind::UnprocessableEntity,
+    ApiErrorKind::TooManyRequests,
+    ApiErrorKind::InternalError,
+    ApiErrorKind::ServiceUnavailable,
+];
+
+/// The upper bounds of each request latency bucket, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Shared metrics state, managed by Rocket.
+#[derive(Default)]
+pub struct Metrics {
+    claims: [AtomicU64; SEARCH_MODES.len()],
+    submissions_accepted: AtomicU64,
+    submissions_rejected: [AtomicU64; REJECTION_KINDS.len()],
+    submissions_rejected_by_reason: [AtomicU64; SubmitRejection::NAMES.len()],
+    /// Requests in each latency bucket, plus one more for everything slower.
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_sum_micros: AtomicU64,
+    latency_count: AtomicU64,
+}
+
+impl Metrics {
+    /// Count fields claimed in a search mode.
+    pub fn record_claims(&self, mode: SearchMode, count: u64) {
+        if let Some(i) = SEARCH_MODES.iter().position(|m| *m == mode) {
+            self.claims[i].fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a submission, either accepted or rejected with some kind of error.
+    /// Rejections are counted by their reason when they have one.
+    pub fn record_submission(
+        &self,
+        rejection: Option<ApiErrorKind>,
+        reason: Option<SubmitRejection>,
+    ) {
+        if let Some(reason) = reason {
+            self.submissions_rejected_by_reason[reason.index()].fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        match rejection.and_then(|kind| REJECTION_KINDS.iter().position(|k| *k == kind)) {
+            Some(i) => self.submissions_rejected[i].fetch_add(1, Ordering::Relaxed),
+            None => self.submissions_accepted.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// Add a request's latency to the histogram.
+    pub fn record_latency(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros.fetch_add(
+            u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render everything in the Prometheus text exposition format.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP nice_claims_total Fields claimed, by search mode."
+        );
+        let _ = writeln!(out, "# TYPE nice_claims_total counter");
+        for (mode, count) in SEARCH_MODES.iter().zip(&self.claims) {
+            let _ = writeln!(
+                out,
+                "nice_claims_total{{mode=\"{}\"}} {}",
+                format!("{mode:?}").to_lowercase(),
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP nice_submissions_accepted_total Submissions that passed validation."
+        );
+        let _ = writeln!(out, "# TYPE nice_submissions_accepted_total counter");
+        let _ = writeln!(
+            out,
+            "nice_submissions_accepted_total {}",
+            self.submissions_accepted.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP nice_submissions_rejected_total Submissions that were rejected, by reason."
+        );
+        let _ = writeln!(out, "# TYPE nice_submissions_rejected_total counter");
+        for (kind, count) in REJECTION_KINDS.iter().zip(&self.submissions_rejected) {
+            let _ = writeln!(
+                out,
+                "nice_submissions_rejected_total{{reason=\"{}\"}} {}",
+                kind.name(),
+                count.load(Ordering::Relaxed)
+            );
+        }
+        for (name, count) in SubmitRejection::NAMES
+            .iter()
+            .zip(&self.submissions_rejected_by_reason)
+        {
+            let _ = writeln!(
+                out,
+                "nice_submissions_rejected_total{{reason=\"{name}\"}} {}",
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP nice_request_duration_seconds Time taken to handle each request."
+        );
+        let _ = writeln!(out, "# TYPE nice_request_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "nice_request_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let count = self.latency_count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "nice_request_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "nice_request_duration_seconds_sum {}",
+            self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1e6
+        );
+        let _ = writeln!(out, "nice_request_duration_seconds_count {count}");
+
+        out
+    }
+}
+
+/// When the request started, stored in the request-local cache.
+struct RequestStart(Instant);
+
+/// How the submit route handled a submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    /// A new submission was saved.
+    Saved,
+    /// The claim was already submitted with the same results, so nothing new was saved.
+    Repeat,
+    /// The submission was rejected, with a reason if it failed validation.
+    Rejected(Option<SubmitRejection>),
+}
+
+/// The submit route's outcome, stored in the request-local cache for the fairing.
+#[derive(Default)]
+struct SubmitOutcomeCell(OnceLock<SubmitOutcome>);
+
+/// A request guard giving the submit route somewhere to leave its outcome.
+pub struct SubmitOutcomeSlot<'r>(&'r SubmitOutcomeCell);
+
+impl SubmitOutcomeSlot<'_> {
+    /// Record the outcome. Only the first one is kept.
+    pub fn set(&self, outcome: SubmitOutcome) {
+        let _ = self.0 .0.set(outcome);
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SubmitOutcomeSlot<'r> {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SubmitOutcomeSlot(
+            req.local_cache(SubmitOutcomeCell::default),
+        ))
+    }
+}
+
+/// Times every request and counts submissions by their outcome.
+pub struct RequestTimingFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTimingFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request timing and metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(metrics) = req.rocket().state::<Metrics>() else {
+            return;
+        };
+        let started = req.local_cache(|| RequestStart(Instant::now()));
+        metrics.record_latency(started.0.elapsed());
+
+        // the route leaves its outcome behind, unless the body was rejected before it ran
+        if req.route().and_then(|r| r.name.as_deref()) == Some("submit") {
+            let outcome = req.local_cache(SubmitOutcomeCell::default).0.get();
+            let reason = match outcome {
+                Some(SubmitOutcome::Repeat) => return,
+                Some(SubmitOutcome::Rejected(reason)) => *reason,
+                Some(SubmitOutcome::Saved) | None => None,
+            };
+            metrics.record_submission(ApiErrorKind::from_status(res.status()), reason);
+        }
+    }
+}
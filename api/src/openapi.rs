@@ -0,0 +1,236 @@
+//! A hand-written `OpenAPI` description of the public endpoints, for third-party client authors.
+//! Field names here must match the serde names on the structs they describe.
+
+use crate::error::{ApiErrorKind, SubmitRejection};
+use rocket::serde::json::{json, Value};
+
+/// Every error kind, so the schema enum can be built from `ApiErrorKind::name`.
+const ERROR_KINDS: [ApiErrorKind; 9] = [
+    ApiErrorKind::BadRequest,
+    ApiErrorKind::Unauthorized,
+    ApiErrorKind::NotFound,
+    ApiErrorKind::Conflict,
+    ApiErrorKind::PayloadTooLarge,
+    ApiErrorKind::UnprocessableEntity,
+    ApiErrorKind::TooManyRequests,
+    ApiErrorKind::InternalError,
+    ApiErrorKind::ServiceUnavailable,
+];
+
+/// A non-negative integer. Some of these are u128 on the server, so clients should use bigints.
+fn big_integer(description: &str) -> Value {
+    json!({ "type": "integer", "minimum": 0, "description": description })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+/// The response for an error status, which always carries an `ApiErrorBody`.
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema_ref("ApiErrorBody") } }
+    })
+}
+
+fn schemas() -> Value {
+    let error_kinds: Vec<&str> = ERROR_KINDS.iter().map(|k| k.name()).collect();
+
+    json!({
+        "DataToClient": {
+            "type": "object",
+            "description": "A field to search, returned from a claim.",
+            "required": ["claim_id", "base", "range_start", "range_end", "range_size"],
+            "properties": {
+                "claim_id": big_integer("Send this back with the submission."),
+                "base": { "type": "integer", "minimum": 0 },
+                "range_start": big_integer("The first number to check."),
+                "range_end": big_integer("The end of the range, exclusive."),
+                "range_size": big_integer("How many numbers are in the range."),
+                "claim_expires_at": {
+                    "type": "string",
+                    "format": "date-time",
+                    "description": "When the field can be handed out to another client."
+                },
+                "server_time": {
+                    "type": "string",
+                    "format": "date-time",
+                    "description": "The server's clock when the field was claimed."
+                },
+            }
+        },
+        "DataToServer": {
+            "type": "object",
+            "description": "The results of searching a field.",
+            "required": ["claim_id", "username", "client_version", "nice_numbers"],
+            "properties": {
+                "claim_id": big_integer("The claim this submission is for."),
+                "username": { "type": "string" },
+                "client_version": { "type": "string", "description": "A semver version string." },
+                "unique_distribution": {
+                    "type": ["array", "null"],
+                    "description": "Required for detailed searches, omitted for niceonly.",
+                    "items": schema_ref("UniquesDistributionSimple")
+                },
+                "nice_numbers": { "type": "array", "items": schema_ref("NiceNumberSimple") },
+            }
+        },
+        "UniquesDistributionSimple": {
+            "type": "object",
+            "required": ["num_uniques", "count"],
+            "properties": {
+                "num_uniques": { "type": "integer", "minimum": 0 },
+                "count": big_integer("How many numbers had this many unique digits."),
+            }
+        },
+        "NiceNumberSimple": {
+            "type": "object",
+            "required": ["number", "num_uniques"],
+            "properties": {
+                "number": big_integer("The number itself."),
+                "num_uniques": { "type": "integer", "minimum": 0 },
+            }
+        },
+        "ApiErrorBody": {
+            "type": "object",
+            "required": ["error", "message"],
+            "properties": {
+                "error": { "type": "string", "enum": error_kinds },
+                "message": { "type": "string", "description": "A human-readable explanation." },
+                "reason": schema_ref("SubmitRejection"),
+            }
+        },
+        "SubmitRejection": {
+            "type": "object",
+            "description": "Why a submission failed validation. Only sent with unprocessable_entity.",
+            "required": ["kind"],
+            "properties": {
+                "kind": {
+                    "type": "string",
+                    "enum": SubmitRejection::NAMES
+                },
+                "num_uniques": { "type": ["integer", "null"], "minimum": 0 },
+                "number": big_integer("The number that failed validation."),
+            }
+        },
+    })
+}
+
+/// Build the `OpenAPI` document for the claim and submit endpoints.
+pub fn spec() -> Value {
+    let search_mode = json!({
+        "name": "mode",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "enum": ["detailed", "niceonly", "nearmiss"] }
+    });
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "nice",
+            "description": "Coordinates the search for square-cube pandigitals.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/claim/{mode}": {
+                "get": {
+                    "summary": "Claim a field to search.",
+                    "parameters": [
+                        search_mode,
+                        {
+                            "name": "base",
+                            "in": "query",
+                            "required": false,
+                            "description": "Only claim a field in this base.",
+                            "schema": { "type": "integer", "minimum": 0 }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The claimed field.",
+                            "content": { "application/json": { "schema": schema_ref("DataToClient") } }
+                        },
+                        "404": error_response("No field is available in the requested base."),
+                        "429": error_response("Too many claims from this address."),
+                        "500": error_response("The field could not be issued."),
+                    }
+                }
+            },
+            "/claim/{mode}/batch/{count}": {
+                "get": {
+                    "summary": "Claim several fields at once.",
+                    "parameters": [
+                        search_mode,
+                        {
+                            "name": "count",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "minimum": 1 }
+                        },
+                        {
+                            "name": "base",
+                            "in": "query",
+                            "required": false,
+                            "description": "Only claim fields in this base.",
+                            "schema": { "type": "integer", "minimum": 0 }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The claimed fields. There may be fewer than requested.",
+                            "content": { "application/json": {
+                                "schema": { "type": "array", "items": schema_ref("DataToClient") }
+                            } }
+                        },
+                        "400": error_response("The count was out of range."),
+                        "429": error_response("Too many claims from this address."),
+                        "500": error_response("The field could not be issued."),
+                    }
+                }
+            },
+            "/submit": {
+                "post": {
+                    "summary": "Submit the results of a search.",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("DataToServer") } }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The submission was accepted.",
+                            "content": { "application/json": { "schema": { "type": "string" } } }
+                        },
+                        "400": error_response("The client version is no longer accepted."),
+                        "404": error_response("The claim does not exist."),
+                        "409": error_response("The claim was already submitted with different results."),
+                        "413": error_response("The submission body was too large."),
+                        "422": error_response("The submission failed validation."),
+                    }
+                }
+            },
+            "/submit/validate": {
+                "post": {
+                    "summary": "Check the results of a search without saving them.",
+                    "description": "Runs every check a real submission would, for testing new clients.",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("DataToServer") } }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The submission would be accepted.",
+                            "content": { "application/json": { "schema": { "type": "string" } } }
+                        },
+                        "400": error_response("The client version is no longer accepted."),
+                        "404": error_response("The claim does not exist."),
+                        "413": error_response("The submission body was too large."),
+                        "422": error_response("The submission failed validation."),
+                    }
+                }
+            },
+        },
+        "components": { "schemas": schemas() },
+    })
+}
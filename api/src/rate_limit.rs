@@ -0,0 +1,104 @@
+//! An in-memory, per-IP token bucket for limiting how often clients can claim.
+
+use crate::client_ip::ClientIp;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// How many claims each IP can make per minute, on average.
+const DEFAULT_RATE_PER_MINUTE: f64 = 30.0;
+
+/// How many claims each IP can make in a quick burst.
+const DEFAULT_BURST: f64 = 10.0;
+
+/// Once this many IPs are tracked, forget the ones that have refilled completely.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// A single IP's bucket.
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Shared limiter state, managed by Rocket.
+pub struct RateLimiter {
+    rate_per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Read a positive number from the environment, falling back to the default.
+fn env_or_default(key: &str, default: f64) -> f64 {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| *v > 0.0)
+        .unwrap_or(default)
+}
+
+impl RateLimiter {
+    /// Build a limiter using `CLAIM_RATE_PER_MINUTE` and `CLAIM_RATE_BURST` if they are set.
+    pub fn from_env() -> Self {
+        RateLimiter {
+            rate_per_second: env_or_default("CLAIM_RATE_PER_MINUTE", DEFAULT_RATE_PER_MINUTE)
+                / 60.0,
+            burst: env_or_default("CLAIM_RATE_BURST", DEFAULT_BURST),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from this IP's bucket.
+    /// Returns false if the bucket is empty.
+    pub fn try_acquire(&self, ip: &str) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.last_refill).as_secs_f64() * self.rate_per_second
+                    < self.burst
+            });
+        }
+
+        let bucket = buckets.entry(ip.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.rate_per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A request guard that only succeeds if the client is under the claim rate limit.
+pub struct ClaimRateLimit;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClaimRateLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Outcome::Success(ClientIp(user_ip)) = req.guard::<ClientIp>().await else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let Outcome::Success(limiter) = req.guard::<&State<RateLimiter>>().await else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        if limiter.try_acquire(&user_ip) {
+            Outcome::Success(ClaimRateLimit)
+        } else {
+            Outcome::Error((Status::TooManyRequests, ()))
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Public summaries of the search progress in each base.
+
+use chrono::{DateTime, Utc};
+use nice_common::distribution_stats::percentiles;
+use nice_common::{BaseRecord, SearchMode, SubmissionRecord};
+use serde::Serialize;
+
+/// The progress and niceness stats for a single base.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct BaseStats {
+    pub base: u32,
+    pub range_size: u128,
+    pub checked_detailed: u128,
+    pub checked_niceonly: u128,
+    pub minimum_cl: u8,
+    pub niceness_mean: Option<f32>,
+    pub niceness_stdev: Option<f32>,
+    pub niceness_p50: Option<f32>,
+    pub niceness_p90: Option<f32>,
+    pub niceness_p99: Option<f32>,
+    pub nice_numbers_count: usize,
+}
+
+impl From<&BaseRecord> for BaseStats {
+    fn from(base: &BaseRecord) -> Self {
+        let niceness_percentiles = percentiles(&base.distribution, &[0.5, 0.9, 0.99]);
+        let niceness_at = |i: usize| niceness_percentiles.as_ref().map(|p| p[i].1);
+        BaseStats {
+            base: base.base,
+            range_size: base.range_size,
+            checked_detailed: base.checked_detailed,
+            checked_niceonly: base.checked_niceonly,
+            minimum_cl: base.minimum_cl,
+            niceness_mean: base.niceness_mean,
+            niceness_stdev: base.niceness_stdev,
+            niceness_p50: niceness_at(0),
+            niceness_p90: niceness_at(1),
+            niceness_p99: niceness_at(2),
+            nice_numbers_count: base.numbers.len(),
+        }
+    }
+}
+
+/// The stats for every base, plus totals across the whole search.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StatsSummary {
+    pub total_nice_numbers: u128,
+    pub bases: Vec<BaseStats>,
+}
+
+/// A trimmed view of a submission for troubleshooting a user's client.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct UserSubmission {
+    pub submit_time: DateTime<Utc>,
+    pub field_id: u128,
+    pub search_mode: SearchMode,
+    pub disqualified: bool,
+    pub suspicious: bool,
+    pub client_version: String,
+}
+
+impl From<&SubmissionRecord> for UserSubmission {
+    fn from(sub: &SubmissionRecord) -> Self {
+        UserSubmission {
+            submit_time: sub.submit_time,
+            field_id: sub.field_id,
+            search_mode: sub.search_mode,
+            disqualified: sub.disqualified,
+            suspicious: sub.suspicious,
+            client_version: sub.client_version.clone(),
+        }
+    }
+}
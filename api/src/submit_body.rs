@@ -0,0 +1,60 @@
+//! A data guard for submission bodies, which get their own size limit.
+//!
+//! Submissions can carry many thousands of numbers, far more than any other request body.
+//! Reading them under the `submit` limit keeps every other JSON route on Rocket's default.
+
+use rocket::data::{self, Data, FromData, Limits, ToByteUnit};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::serde::json::serde_json;
+use rocket::serde::DeserializeOwned;
+use std::ops::Deref;
+
+/// The key in Rocket's limits config that submission bodies are read under.
+pub const SUBMIT_LIMIT_KEY: &str = "submit";
+
+/// A JSON body read under the `submit` limit instead of the `json` one.
+pub struct SubmitJson<T>(pub T);
+
+/// Get the body limit for submissions, falling back to the regular JSON limit if unset.
+pub fn submit_limit(req: &Request<'_>) -> u64 {
+    req.limits()
+        .get(SUBMIT_LIMIT_KEY)
+        .unwrap_or(Limits::JSON)
+        .as_u64()
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for SubmitJson<T> {
+    type Error = String;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let body = match data.open(submit_limit(req).bytes()).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                return data::Outcome::Error((
+                    Status::PayloadTooLarge,
+                    "data limit exceeded".to_string(),
+                ))
+            }
+            Err(e) => return data::Outcome::Error((Status::BadRequest, e.to_string())),
+        };
+
+        // same statuses as rocket's own Json guard
+        match serde_json::from_str(&body) {
+            Ok(value) => data::Outcome::Success(SubmitJson(value)),
+            Err(e) if e.classify() == serde_json::error::Category::Data => {
+                data::Outcome::Error((Status::UnprocessableEntity, e.to_string()))
+            }
+            Err(e) => data::Outcome::Error((Status::BadRequest, e.to_string())),
+        }
+    }
+}
+
+impl<T> Deref for SubmitJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
@@ -0,0 +1,23 @@
+//! How fast a field can be searched before the submission is flagged as suspicious.
+
+use nice_common::SUSPICIOUS_NUMBERS_PER_SECOND;
+use std::env;
+
+/// Set this to a number of numbers per second to override the default ceiling.
+const SUSPICIOUS_RATE_VAR: &str = "NICE_SUSPICIOUS_RATE";
+
+/// The resolved ceiling in numbers per second, managed by Rocket.
+pub struct SuspiciousRate(pub f64);
+
+impl SuspiciousRate {
+    /// Read the ceiling from `NICE_SUSPICIOUS_RATE`, defaulting to `SUSPICIOUS_NUMBERS_PER_SECOND`.
+    pub fn from_env() -> Self {
+        let rate = env::var(SUSPICIOUS_RATE_VAR).ok().map(|v| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|r| r.is_finite() && *r > 0.0)
+                .unwrap_or_else(|| panic!("{SUSPICIOUS_RATE_VAR} must be a positive number"))
+        });
+        SuspiciousRate(rate.unwrap_or(SUSPICIOUS_NUMBERS_PER_SECOND))
+    }
+}
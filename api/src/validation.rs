@@ -0,0 +1,175 @@
+//! Check a submission against its claim before anything is saved.
+
+use crate::client_version::check_client_version;
+use crate::error::{
+    internal_error, not_found_error, rejected_submission_error, ApiError, SubmitRejection,
+};
+use nice_common::db_util::{get_claim_by_id, get_field_by_id, PgPooledConnection};
+use nice_common::distribution_stats::{expand_distribution, shrink_distribution};
+use nice_common::number_stats::{
+    expand_numbers, find_distribution_count_mismatch, find_duplicate_number,
+    find_number_outside_range, find_unique_count_mismatch, shrink_numbers,
+};
+use nice_common::{
+    get_near_miss_cutoff, ClaimRecord, DataToServer, FieldRecord, FieldSize, NiceNumber,
+    SearchMode, SubmissionRecord, UniquesDistribution, NEAR_MISS_DISTANCE,
+};
+
+/// A submission that passed every check, with the records it was checked against.
+pub struct ValidatedSubmission {
+    pub claim_record: ClaimRecord,
+    pub field_record: FieldRecord,
+    pub distribution: Option<Vec<UniquesDistribution>>,
+    pub numbers: Vec<NiceNumber>,
+}
+
+/// Run every check a submission must pass before it can be saved.
+/// Returns the expanded distribution and numbers, ready to insert.
+pub fn validate_submission(
+    conn: &mut PgPooledConnection,
+    submit_data: &DataToServer,
+) -> Result<ValidatedSubmission, ApiError> {
+    // reject clients that are too old to trust
+    check_client_version(&submit_data.client_version)?;
+
+    // get claim record
+    let claim_record = get_claim_by_id(conn, submit_data.claim_id)
+        .map_err(|e| not_found_error(format!("Claim #{} not found: {e}", submit_data.claim_id)))?;
+
+    // get field record (for base)
+    let field_record = get_field_by_id(conn, claim_record.field_id).map_err(internal_error)?;
+    let base = field_record.base;
+
+    // reject numbers reported twice, which could otherwise hide a bad distribution
+    if let Some(number) = find_duplicate_number(&submit_data.nice_numbers) {
+        return Err(rejected_submission_error(
+            SubmitRejection::DuplicateNumber { number },
+            format!("Number {number} was submitted more than once."),
+        ));
+    }
+
+    // reject numbers from outside the field, which would inflate the base
+    let field_range = FieldSize::new(field_record.range_start, field_record.range_end);
+    if let Some(number) = find_number_outside_range(&submit_data.nice_numbers, &field_range) {
+        return Err(rejected_submission_error(
+            SubmitRejection::OutOfRange { number },
+            format!(
+                "Number {number} is outside of field #{} ({} to {}).",
+                field_record.field_id, field_record.range_start, field_record.range_end
+            ),
+        ));
+    }
+
+    // expand nice numbers
+    let numbers_expanded = expand_numbers(&submit_data.nice_numbers, base);
+
+    let distribution = match claim_record.search_mode {
+        // no checks, honor system
+        SearchMode::Niceonly => None,
+        SearchMode::NearMiss => {
+            // check each near miss provided
+            check_unique_counts(&numbers_expanded)?;
+            let min_uniques = base.saturating_sub(NEAR_MISS_DISTANCE);
+            for n in &numbers_expanded {
+                if n.num_uniques < min_uniques {
+                    return Err(rejected_submission_error(
+                        SubmitRejection::NotNearMiss { number: n.number },
+                        format!(
+                            "Number {} is not a near miss ({} uniques, minimum is {min_uniques}).",
+                            n.number, n.num_uniques
+                        ),
+                    ));
+                }
+            }
+            None
+        }
+        SearchMode::Detailed => {
+            // run through some basic validity tests
+            let Some(distribution) = &submit_data.unique_distribution else {
+                return Err(rejected_submission_error(
+                    SubmitRejection::MissingDistribution,
+                    "Unique distribution must be present for detailed searches.",
+                ));
+            };
+
+            // expand distribution
+            let distribution_expanded = expand_distribution(distribution, base);
+
+            // check distribution count sums to range_size
+            let dist_total_count = distribution.iter().fold(0, |acc, d| acc + d.count);
+            if dist_total_count != field_record.range_size {
+                return Err(rejected_submission_error(
+                    SubmitRejection::DistributionSumMismatch,
+                    format!(
+                        "Total distribution count is incorrect (submitted {}, range was {}).",
+                        dist_total_count, field_record.range_size
+                    ),
+                ));
+            }
+
+            // get the near-miss cutoff, always the standard one regardless of the client's
+            let num_uniques_cutoff = get_near_miss_cutoff(base, None);
+
+            // check count of nice numbers against distribution
+            if let Some((num_uniques, count_numbers, count_claimed)) =
+                find_distribution_count_mismatch(
+                    &distribution_expanded,
+                    &numbers_expanded,
+                    num_uniques_cutoff,
+                )
+            {
+                return Err(rejected_submission_error(SubmitRejection::NiceCountMismatch { num_uniques: Some(num_uniques) }, format!(
+                    "Count of nice numbers with {num_uniques} uniques does not match distribution (submitted {count_numbers}, distribution claimed {count_claimed})."
+                )));
+            }
+
+            // check total number of nice numbers
+            let num_total_count = numbers_expanded.len();
+            let dist_total_count_above_cutoff = distribution
+                .iter()
+                .filter(|d| d.num_uniques > num_uniques_cutoff)
+                .fold(0, |acc, d| acc + d.count);
+            if num_total_count as u128 != dist_total_count_above_cutoff {
+                return Err(rejected_submission_error(SubmitRejection::NiceCountMismatch { num_uniques: None }, format!(
+                    "Count of nice numbers does not match distribution (submitted {num_total_count}, distribution claimed {dist_total_count_above_cutoff})."
+                )));
+            }
+
+            // check each nice number provided
+            check_unique_counts(&numbers_expanded)?;
+
+            Some(distribution_expanded)
+        }
+    };
+
+    Ok(ValidatedSubmission {
+        claim_record,
+        field_record,
+        distribution,
+        numbers: numbers_expanded,
+    })
+}
+
+/// Recalculate the unique count of every number and reject the submission on any mismatch.
+fn check_unique_counts(numbers: &[NiceNumber]) -> Result<(), ApiError> {
+    match find_unique_count_mismatch(numbers) {
+        Some((n, calculated_num_uniques)) => Err(rejected_submission_error(
+            SubmitRejection::UniqueCountMismatch { number: n.number },
+            format!(
+                "Unique count for {} is incorrect (submitted as {}, sever calculated {}).",
+                n.number, n.num_uniques, calculated_num_uniques
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check whether a repeated submission carries the same results as the one already saved.
+/// Only detailed submissions keep their distribution, so it's only compared when one was saved.
+pub fn matches_saved_submission(saved: &SubmissionRecord, submit_data: &DataToServer) -> bool {
+    saved.username == submit_data.username
+        && shrink_numbers(&saved.numbers) == submit_data.nice_numbers
+        && saved.distribution.as_ref().is_none_or(|distribution| {
+            submit_data.unique_distribution.as_ref() == Some(&shrink_distribution(distribution))
+        })
+}
@@ -0,0 +1,60 @@
+//! Save and restore progress on a field so long runs can survive a crash or reboot.
+
+use nice_common::{DataToClient, FieldResults, SearchMode};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to pick up a claim where we left off.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub mode: SearchMode,
+    pub claim_data: DataToClient,
+    /// The end of the last chunk that was fully processed.
+    pub next_start: u128,
+    /// The results aggregated from every chunk processed so far.
+    pub results: FieldResults,
+}
+
+/// The checkpoint location if none is specified: `~/.cache/nice/checkpoint.json`.
+pub fn default_path() -> PathBuf {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map_or_else(|| PathBuf::from("."), PathBuf::from);
+    home.join(".cache").join("nice").join("checkpoint.json")
+}
+
+/// Read a checkpoint from disk.
+/// Returns None if there is no checkpoint or it could not be read.
+pub fn load(path: &Path) -> Option<Checkpoint> {
+    let contents = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(e) => {
+            println!("Ignoring unreadable checkpoint {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Write a checkpoint to disk.
+/// Writes to a temporary file first so a crash mid-write can't corrupt the last checkpoint.
+pub fn save(path: &Path, checkpoint: &Checkpoint) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string(checkpoint).map_err(|e| e.to_string())?;
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, path).map_err(|e| e.to_string())
+}
+
+/// Delete the checkpoint once the claim has been submitted.
+pub fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            println!("Failed to remove checkpoint {}: {e}", path.display());
+        }
+    }
+}
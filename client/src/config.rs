@@ -0,0 +1,90 @@
+//! Load default settings from a JSON file so long command lines can be kept on disk.
+//!
+//! Settings are resolved in order of precedence: CLI flags, then environment variables,
+//! then the config file, then the built-in defaults.
+
+use super::{parse_cutoff_percent, Cli};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use nice_common::SearchMode;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Every setting that can be read from the config file, all optional.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub mode: Option<String>,
+    pub api_base: Option<String>,
+    pub username: Option<String>,
+    pub repeat: Option<bool>,
+    pub quiet: Option<bool>,
+    pub verbose: Option<bool>,
+    pub no_progress: Option<bool>,
+    pub max_retries: Option<u32>,
+    pub base: Option<u32>,
+    pub near_miss_cutoff: Option<f32>,
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Read and parse a config file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {e}", path.display()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse config {}: {e}", path.display()))
+    }
+
+    /// Fill in any setting that was not given on the command line or through the environment.
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) -> Result<(), String> {
+        // only settings left at their defaults are taken from the file
+        let unset = |id: &str| {
+            matches
+                .value_source(id)
+                .is_none_or(|source| source == ValueSource::DefaultValue)
+        };
+
+        if let Some(mode) = self.mode.filter(|_| unset("mode")) {
+            cli.mode = SearchMode::from_str(&mode, true)
+                .map_err(|_| format!("Unknown search mode {mode} in config."))?;
+        }
+        if let Some(api_base) = self.api_base.filter(|_| unset("api_base")) {
+            cli.api_base = api_base;
+        }
+        if let Some(username) = self.username.filter(|_| unset("username")) {
+            cli.username = username;
+        }
+        if let Some(repeat) = self.repeat.filter(|_| unset("repeat")) {
+            cli.repeat = repeat;
+        }
+        if let Some(quiet) = self.quiet.filter(|_| unset("quiet")) {
+            cli.quiet = quiet;
+        }
+        if let Some(verbose) = self.verbose.filter(|_| unset("verbose")) {
+            cli.verbose = verbose;
+        }
+        if let Some(no_progress) = self.no_progress.filter(|_| unset("no_progress")) {
+            cli.no_progress = no_progress;
+        }
+        if let Some(max_retries) = self.max_retries.filter(|_| unset("max_retries")) {
+            cli.max_retries = max_retries;
+        }
+        if let Some(base) = self.base.filter(|_| unset("base")) {
+            if base < 2 {
+                return Err(format!("Base {base} in config must be at least 2."));
+            }
+            cli.base = Some(base);
+        }
+        if let Some(cutoff) = self.near_miss_cutoff.filter(|_| unset("near_miss_cutoff")) {
+            let cutoff = parse_cutoff_percent(&cutoff.to_string())
+                .map_err(|e| format!("Near-miss cutoff in config {e}."))?;
+            cli.near_miss_cutoff = Some(cutoff);
+        }
+        if let Some(checkpoint) = self.checkpoint.filter(|_| unset("checkpoint")) {
+            cli.checkpoint = Some(checkpoint);
+        }
+        Ok(())
+    }
+}
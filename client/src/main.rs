@@ -3,31 +3,66 @@
 #![warn(clippy::all, clippy::pedantic)]
 
 extern crate nice_common;
-use nice_common::benchmark::{get_benchmark_field, BenchmarkMode};
+use nice_common::benchmark::{get_benchmark_field, BenchmarkMode, BenchmarkResult};
 use nice_common::client_api::get_field_from_server;
 use nice_common::client_api::submit_field_to_server;
-use nice_common::client_process::process_detailed;
-use nice_common::client_process::process_niceonly;
-use nice_common::{DataToServer, SearchMode};
+use nice_common::client_api::validate_field_with_server;
+use nice_common::client_process::process_range_count_only;
+use nice_common::client_process::process_range_detailed;
+use nice_common::client_process::process_range_near_miss;
+use nice_common::client_process::process_range_niceonly_strided;
+use nice_common::distribution_stats;
+use nice_common::generate_fields::break_range_into_fields;
+use nice_common::{
+    get_near_miss_cutoff, DataToClient, DataToServer, FieldResults, SearchMode, CLIENT_VERSION,
+    NEAR_MISS_CUTOFF_PERCENT, NEAR_MISS_DISTANCE,
+};
 
 extern crate serde_json;
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
+mod checkpoint;
+mod config;
+mod output;
+mod progress;
+use checkpoint::Checkpoint;
+use config::ConfigFile;
+use output::OutputFormat;
+use progress::Progress;
+
+/// How many numbers to process between each checkpoint.
+const CHUNK_SIZE: u128 = 10_000_000;
+
+/// Set on the first Ctrl-C, telling the client to stop after the current chunk.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cli {
     /// The checkout mode to use
     #[arg(value_enum, default_value = "detailed")]
     mode: SearchMode,
 
     /// The base API URL to connect to
-    #[arg(long, default_value = "https://api.nicenumbers.net")]
+    #[arg(
+        long,
+        env = "NICE_API_BASE",
+        default_value = "https://api.nicenumbers.net"
+    )]
     api_base: String,
 
     /// The username to send alongside your contribution
-    #[arg(short, long, default_value = "anonymous")]
+    #[arg(short, long, env = "NICE_USERNAME", default_value = "anonymous")]
     username: String,
 
     /// Run indefinitely with the current settings
@@ -42,21 +77,501 @@ pub struct Cli {
     #[arg(short, long)]
     verbose: bool,
 
+    /// Don't print progress and ETA after each chunk
+    #[arg(long)]
+    no_progress: bool,
+
     /// Run an offline benchmark
     #[arg(short, long)]
     benchmark: Option<BenchmarkMode>,
+
+    /// Run every offline benchmark in sequence
+    #[arg(long, conflicts_with_all = ["benchmark", "range_start"])]
+    bench_all: bool,
+
+    /// Write the benchmark results to a JSON file
+    #[arg(long)]
+    bench_output: Option<PathBuf>,
+
+    /// How many untimed benchmark runs to do before measuring
+    #[arg(long, default_value_t = 0)]
+    benchmark_warmup: u32,
+
+    /// How many timed benchmark runs to report the median, minimum, and maximum of
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    benchmark_iterations: u32,
+
+    /// How many times to retry a request after a network or server error
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
+
+    /// Process a custom range offline instead of claiming from the server
+    #[arg(long, requires_all = ["range_end", "base"], conflicts_with = "benchmark")]
+    range_start: Option<u128>,
+
+    /// The end of the custom offline range (exclusive)
+    #[arg(long, requires = "range_start")]
+    range_end: Option<u128>,
+
+    /// The base to use for the custom offline range.
+    /// Without a custom range, only claim fields in this base from the server
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    base: Option<u32>,
+
+    /// Only count the nice numbers in the custom offline range and print the total
+    #[arg(long, requires = "range_start", conflicts_with_all = ["output", "benchmark", "bench_all"])]
+    count_only: bool,
+
+    /// Report detailed-search numbers with at least this fraction of unique digits (default 0.9).
+    /// Only numbers above the default are submitted. When online, a stricter cutoff still
+    /// searches down to the default for the server and only trims the local output
+    #[arg(long, value_parser = parse_cutoff_percent)]
+    near_miss_cutoff: Option<f32>,
+
+    /// Where to save progress so an interrupted field can be resumed.
+    /// Defaults to ~/.cache/nice/checkpoint.json, use a unique path for each parallel client
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Also write the results to a local file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// The format to write the output file in
+    #[arg(long, value_enum, default_value = "json", requires = "output")]
+    format: OutputFormat,
+
+    /// Have the server check the results without saving them, for testing new clients.
+    /// The results stay in the checkpoint, so a later run without this flag submits them
+    #[arg(long, conflicts_with_all = ["benchmark", "bench_all", "range_start"])]
+    dry_run: bool,
+
+    /// Read default settings from a JSON file.
+    /// Flags and environment variables take precedence over the file, which takes precedence over the defaults
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a shell completion script to stdout
+    #[command(hide = true)]
+    Completions {
+        /// The shell to generate completions for
+        shell: Shell,
+    },
+}
+
+impl Cli {
+    /// Whether this run should stay offline and never contact the server.
+    fn is_offline(&self) -> bool {
+        self.benchmark.is_some() || self.bench_all || self.range_start.is_some()
+    }
+
+    /// The near-miss cutoff to search with. The server checks detailed results against the
+    /// default cutoff, so online searches never go stricter than that.
+    fn search_cutoff(&self) -> Option<f32> {
+        if self.is_offline() {
+            self.near_miss_cutoff
+        } else {
+            self.near_miss_cutoff
+                .map(|cutoff| cutoff.min(NEAR_MISS_CUTOFF_PERCENT))
+        }
+    }
+
+    /// Whether to run the benchmarks on their own and report their stats,
+    /// rather than processing the benchmark like any other field.
+    fn is_benchmark_run(&self) -> bool {
+        self.bench_all
+            || self.bench_output.is_some()
+            || self.benchmark_warmup > 0
+            || self.benchmark_iterations > 1
+    }
+}
+
+/// Parse a near-miss cutoff, which must be a fraction between 0 and 1.
+fn parse_cutoff_percent(s: &str) -> Result<f32, String> {
+    let cutoff: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&cutoff) {
+        Ok(cutoff)
+    } else {
+        Err("must be between 0 and 1".to_string())
+    }
+}
+
+/// Drop any detailed numbers kept below a cutoff, e.g. the server's own before submitting.
+fn trim_to_cutoff(
+    mut submit_data: DataToServer,
+    mode: SearchMode,
+    base: u32,
+    cutoff_percent: Option<f32>,
+) -> DataToServer {
+    if mode == SearchMode::Detailed {
+        let cutoff = get_near_miss_cutoff(base, cutoff_percent);
+        submit_data.nice_numbers.retain(|n| n.num_uniques > cutoff);
+    }
+    submit_data
+}
+
+/// Add the results from one chunk into the running total for the field.
+fn merge_results(total: &mut FieldResults, chunk: FieldResults) {
+    let distribution = std::mem::take(&mut total.distribution);
+    total.distribution = distribution_stats::merge([distribution, chunk.distribution].into_iter());
+    total.nice_numbers.extend(chunk.nice_numbers);
+    total.processed_count += chunk.processed_count;
+}
+
+/// Stop after the current chunk on the first Ctrl-C, exit immediately on the second.
+/// Either way the checkpoint keeps every finished chunk so the field can be resumed.
+fn install_interrupt_handler() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+        println!("Interrupted, stopping after the current chunk. Press Ctrl-C again to exit now.");
+    })
+    .expect("Error setting Ctrl-C handler");
 }
 
 fn main() {
     // parse args from command line
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(config_path) = cli.config.clone() {
+        if let Err(e) = ConfigFile::load(&config_path).and_then(|c| c.apply(&mut cli, &matches)) {
+            Cli::command().error(ErrorKind::InvalidValue, e).exit();
+        }
+    }
+    if let Some(Command::Completions { shell }) = cli.command {
+        clap_complete::generate(
+            shell,
+            &mut Cli::command(),
+            env!("CARGO_BIN_NAME"),
+            &mut io::stdout(),
+        );
+        return;
+    }
+    if let (Some(range_start), Some(range_end)) = (cli.range_start, cli.range_end) {
+        if range_end <= range_start {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "--range-end must be greater than --range-start",
+                )
+                .exit();
+        }
+    }
+    if cli.is_benchmark_run() && cli.benchmark.is_none() && !cli.bench_all {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the benchmark options require --benchmark or --bench-all",
+            )
+            .exit();
+    }
+    install_interrupt_handler();
+
+    // a count-only scan never builds or submits any results
+    if cli.count_only {
+        if let Err(e) = run_count_only(&cli) {
+            println!("Error: {e}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    // benchmarks that are being repeated, recorded, or run together are handled separately
+    if cli.is_benchmark_run() {
+        if let Err(e) = run_benchmarks(&cli) {
+            println!("Error: {e}");
+            process::exit(1);
+        }
+        return;
+    }
 
-    // check whether to query the server for a search range or use the benchmark
-    let claim_data = if let Some(benchmark) = cli.benchmark {
-        get_benchmark_field(benchmark)
+    loop {
+        match process_field(&cli) {
+            Ok(()) => {}
+            // the checkpoint would only be rejected again, so stop instead of repeating it
+            Err(FieldError::Rejected(e)) => {
+                println!("Error: {e}");
+                process::exit(1);
+            }
+            Err(FieldError::Other(e)) => {
+                println!("Error: {e}");
+                if !cli.repeat {
+                    process::exit(1);
+                }
+            }
+        }
+
+        // repeat if requested
+        if !cli.repeat || INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+}
+
+/// Process the rest of the field in chunks, saving a checkpoint after each one.
+/// Returns the end of the last chunk processed, which is short of the field end if interrupted.
+fn process_chunks(
+    cli: &Cli,
+    claim_data: &DataToClient,
+    field_results: &mut FieldResults,
+    next_start: u128,
+    checkpoint_path: &Path,
+) -> u128 {
+    let chunks = break_range_into_fields(next_start, claim_data.range_end, CHUNK_SIZE);
+    let mut completed_end = next_start;
+    let mut progress = (!cli.quiet && !cli.no_progress)
+        .then(|| Progress::new(claim_data.range_size, next_start - claim_data.range_start));
+    for chunk in chunks {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+        let chunk_results = match cli.mode {
+            SearchMode::Detailed => process_range_detailed(
+                chunk.range_start,
+                chunk.range_end,
+                claim_data.base,
+                cli.search_cutoff(),
+            ),
+            SearchMode::Niceonly => {
+                process_range_niceonly_strided(chunk.range_start, chunk.range_end, claim_data.base)
+            }
+            SearchMode::NearMiss => process_range_near_miss(
+                chunk.range_start,
+                chunk.range_end,
+                claim_data.base,
+                NEAR_MISS_DISTANCE,
+            ),
+        };
+        merge_results(field_results, chunk_results);
+        completed_end = chunk.range_end;
+        if let Some(progress) = &mut progress {
+            progress.update(chunk.range_size);
+        }
+
+        if !cli.is_offline() {
+            let saved = Checkpoint {
+                mode: cli.mode,
+                claim_data: claim_data.clone(),
+                next_start: chunk.range_end,
+                results: field_results.clone(),
+            };
+            if let Err(e) = checkpoint::save(checkpoint_path, &saved) {
+                println!(
+                    "Failed to save checkpoint {}: {e}",
+                    checkpoint_path.display()
+                );
+            }
+        }
+    }
+    completed_end
+}
+
+/// Process a benchmark field once, returning how many numbers were processed and how long it took.
+fn time_benchmark(cli: &Cli, claim_data: &DataToClient) -> (u128, f64) {
+    let before = Instant::now();
+    let mut field_results = FieldResults::default();
+    let completed_end = process_chunks(
+        cli,
+        claim_data,
+        &mut field_results,
+        claim_data.range_start,
+        &checkpoint::default_path(),
+    );
+    (
+        completed_end - claim_data.range_start,
+        before.elapsed().as_secs_f64(),
+    )
+}
+
+/// Run one or all of the benchmarks, then write the results to a JSON file if requested.
+/// Each benchmark is repeated and the median, minimum, and maximum rates are reported.
+fn run_benchmarks(cli: &Cli) -> Result<(), String> {
+    let modes = match cli.benchmark {
+        Some(benchmark) => vec![benchmark],
+        None => BenchmarkMode::value_variants().to_vec(),
+    };
+
+    let mut results = Vec::new();
+    for benchmark in modes {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+        let claim_data = get_benchmark_field(benchmark);
+        if !cli.quiet {
+            println!("Running {benchmark:?} benchmark");
+        }
+
+        // warm up first, then time each iteration
+        for _ in 0..cli.benchmark_warmup {
+            time_benchmark(cli, &claim_data);
+        }
+        let mut runs: Vec<(u128, f64)> = (0..cli.benchmark_iterations)
+            .map(|_| time_benchmark(cli, &claim_data))
+            .collect();
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        runs.sort_by(|a, b| (a.0 as f64 / a.1).total_cmp(&(b.0 as f64 / b.1)));
+        #[allow(clippy::cast_precision_loss)]
+        let rate = |(processed, elapsed_secs): (u128, f64)| processed as f64 / elapsed_secs;
+        let (range_size, elapsed_secs) = runs[runs.len() / 2];
+        let numbers_per_second = rate(runs[runs.len() / 2]);
+        let min_numbers_per_second = rate(runs[0]);
+        let max_numbers_per_second = rate(runs[runs.len() - 1]);
+        println!(
+            "Elapsed time:    {elapsed_secs:.3}s (median of {})",
+            runs.len()
+        );
+        println!(
+            "Processing rate: {numbers_per_second:.3e} numbers/second (min {min_numbers_per_second:.3e}, max {max_numbers_per_second:.3e})"
+        );
+
+        results.push(BenchmarkResult {
+            benchmark,
+            search_mode: cli.mode,
+            base: claim_data.base,
+            range_size,
+            iterations: cli.benchmark_iterations,
+            elapsed_secs,
+            numbers_per_second,
+            min_numbers_per_second,
+            max_numbers_per_second,
+        });
+    }
+
+    if let Some(path) = &cli.bench_output {
+        let contents = serde_json::to_string_pretty(&results).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Count the nice numbers in the custom range and print the total.
+/// Counted in chunks so Ctrl-C can stop it, a partial count is reported as an error.
+fn run_count_only(cli: &Cli) -> Result<(), String> {
+    let (Some(range_start), Some(range_end), Some(base)) =
+        (cli.range_start, cli.range_end, cli.base)
+    else {
+        unreachable!("clap requires a full custom range for --count-only");
+    };
+    let before = Instant::now();
+    let mut count = 0;
+    for chunk in break_range_into_fields(range_start, range_end, CHUNK_SIZE) {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Err(format!(
+                "Interrupted, only counted {count} nice numbers from {range_start} to {}.",
+                chunk.range_start
+            ));
+        }
+        count += process_range_count_only(chunk.range_start, chunk.range_end, base);
+    }
+    if cli.verbose {
+        println!("Elapsed time:    {:.3?}", before.elapsed());
+    }
+    println!("{count}");
+    Ok(())
+}
+
+/// Why a field could not be finished.
+enum FieldError {
+    /// The server refused the results, so sending them again would be refused too.
+    Rejected(String),
+    /// Anything else, which may work on the next try.
+    Other(String),
+}
+
+/// Submit the results and clear the checkpoint once the server has accepted them.
+/// The checkpoint is kept after any error, including a rejection, so the computed results
+/// aren't lost and can be submitted again once the client or server is fixed.
+/// On a dry run nothing is saved by the server, so the checkpoint is always kept.
+fn submit_results(
+    cli: &Cli,
+    submit_data: &DataToServer,
+    checkpoint_path: &Path,
+) -> Result<(), FieldError> {
+    let response = if cli.dry_run {
+        validate_field_with_server(&cli.api_base, submit_data, cli.max_retries)
     } else {
-        get_field_from_server(&cli.mode, &cli.api_base)
+        submit_field_to_server(&cli.api_base, submit_data, cli.max_retries)
     };
+    match response {
+        Ok(response) => {
+            println!("Server response: {response}");
+            if !cli.dry_run {
+                checkpoint::remove(checkpoint_path);
+            }
+            Ok(())
+        }
+        Err(e) if e.is_rejection() => Err(FieldError::Rejected(format!(
+            "Submission rejected: {e}. The results are kept in {}.",
+            checkpoint_path.display()
+        ))),
+        Err(e) => Err(FieldError::Other(e.to_string())),
+    }
+}
+
+/// Pick the field to process: the benchmark or custom range, a saved checkpoint, or a new claim.
+/// Returns the field, any results already saved for it, and where to resume from.
+fn get_field(
+    cli: &Cli,
+    checkpoint_path: &Path,
+) -> Result<(DataToClient, FieldResults, u128), String> {
+    let field = if let Some(benchmark) = cli.benchmark {
+        let claim_data = get_benchmark_field(benchmark);
+        let range_start = claim_data.range_start;
+        (claim_data, FieldResults::default(), range_start)
+    } else if let (Some(range_start), Some(range_end), Some(base)) =
+        (cli.range_start, cli.range_end, cli.base)
+    {
+        let claim_data = DataToClient {
+            claim_id: 0,
+            base,
+            range_start,
+            range_end,
+            range_size: range_end - range_start,
+            claim_expires_at: None,
+            server_time: None,
+        };
+        (claim_data, FieldResults::default(), range_start)
+    } else if let Some(saved) = checkpoint::load(checkpoint_path).filter(|saved| {
+        saved.mode == cli.mode && cli.base.is_none_or(|base| saved.claim_data.base == base)
+    }) {
+        if !cli.quiet {
+            println!(
+                "Resuming claim #{} from checkpoint at {}",
+                saved.claim_data.claim_id, saved.next_start
+            );
+        }
+        (saved.claim_data, saved.results, saved.next_start)
+    } else {
+        let claim_data = get_field_from_server(&cli.mode, &cli.api_base, cli.base, cli.max_retries)
+            .map_err(|e| e.to_string())?;
+        let range_start = claim_data.range_start;
+        (claim_data, FieldResults::default(), range_start)
+    };
+    Ok(field)
+}
+
+/// Get a field, process it, and submit the results.
+/// If submitting fails the checkpoint is kept, so the next run will try to submit it again.
+fn process_field(cli: &Cli) -> Result<(), FieldError> {
+    let checkpoint_path = cli
+        .checkpoint
+        .clone()
+        .unwrap_or_else(checkpoint::default_path);
+
+    // check whether to use the benchmark or a custom range, resume a checkpoint, or query the server
+    let (claim_data, mut field_results, next_start) =
+        get_field(cli, &checkpoint_path).map_err(FieldError::Other)?;
 
     // print some debug info
     if !cli.quiet {
@@ -69,10 +584,38 @@ fn main() {
     // start the timer for benchmarking
     let before = Instant::now();
 
-    // process range & compile results
-    let submit_data: DataToServer = match cli.mode {
-        SearchMode::Detailed => process_detailed(&claim_data, &cli.username),
-        SearchMode::Niceonly => process_niceonly(&claim_data, &cli.username),
+    // process range in chunks, saving progress after each one
+    let completed_end = process_chunks(
+        cli,
+        &claim_data,
+        &mut field_results,
+        next_start,
+        &checkpoint_path,
+    );
+
+    // the server can't verify part of a field, so leave it in the checkpoint to finish later
+    if completed_end < claim_data.range_end {
+        if cli.is_offline() {
+            println!("Stopped at {completed_end}, discarding partial results.");
+        } else {
+            println!(
+                "Stopped at {completed_end}, progress saved to {}. Run again to resume.",
+                checkpoint_path.display()
+            );
+        }
+        return Ok(());
+    }
+
+    // compile results
+    let submit_data = DataToServer {
+        claim_id: claim_data.claim_id,
+        username: cli.username.clone(),
+        client_version: CLIENT_VERSION.to_string(),
+        unique_distribution: match cli.mode {
+            SearchMode::Detailed => Some(field_results.distribution),
+            SearchMode::Niceonly | SearchMode::NearMiss => None,
+        },
+        nice_numbers: field_results.nice_numbers,
     };
 
     // stop the benchmarking timer
@@ -92,18 +635,40 @@ fn main() {
         println!("Elapsed time:    {:.3?}", before.elapsed());
         println!(
             "Processing rate: {:.3e} numbers/second",
-            claim_data.range_size as f64 / elapsed_seconds
+            (completed_end - next_start) as f64 / elapsed_seconds
+        );
+        println!(
+            "Numbers checked: {} of {} (the rest were skipped by filters)",
+            field_results.processed_count,
+            completed_end - claim_data.range_start
         );
     }
 
-    // submit the results
-    if cli.benchmark.is_none() {
-        submit_field_to_server(&cli.api_base, submit_data);
+    // save the results locally, trimmed to the requested cutoff if the search went looser
+    if let Some(output_path) = &cli.output {
+        let output_data = trim_to_cutoff(
+            submit_data.clone(),
+            cli.mode,
+            claim_data.base,
+            cli.near_miss_cutoff,
+        );
+        if let Err(e) =
+            output::write_results(output_path, cli.format, claim_data.base, &output_data)
+        {
+            println!("Failed to write output {}: {e}", output_path.display());
+        }
     }
 
-    // repeat if requested
-    #[allow(clippy::main_recursion)]
-    if cli.repeat {
-        main();
+    // submit the results
+    if !cli.is_offline() {
+        if claim_data.is_claim_expired() {
+            println!(
+                "WARNING: Claim #{} has expired, the field may have been handed to another client.",
+                claim_data.claim_id
+            );
+        }
+        let submit_data = trim_to_cutoff(submit_data, cli.mode, claim_data.base, None);
+        submit_results(cli, &submit_data, &checkpoint_path)?;
     }
+    Ok(())
 }
@@ -0,0 +1,65 @@
+//! Write processing results to local files for offline analysis.
+
+use clap::ValueEnum;
+use nice_common::DataToServer;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File formats the results can be written in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The same JSON that is sent to the server, plus the base.
+    Json,
+    /// One row per nice number, with the distribution in a second file.
+    Csv,
+}
+
+/// Get the path for the distribution CSV that accompanies the numbers CSV.
+/// For `results.csv` this is `results_distribution.csv`.
+fn distribution_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "results".into(), |s| s.to_string_lossy());
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}_distribution.{}", ext.to_string_lossy()),
+        None => format!("{stem}_distribution"),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Write the results to disk in the requested format.
+pub fn write_results(
+    path: &Path,
+    format: OutputFormat,
+    base: u32,
+    submit_data: &DataToServer,
+) -> Result<(), String> {
+    match format {
+        OutputFormat::Json => {
+            let mut value = serde_json::to_value(submit_data).map_err(|e| e.to_string())?;
+            value["base"] = base.into();
+            let contents = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+            fs::write(path, contents).map_err(|e| e.to_string())
+        }
+        OutputFormat::Csv => {
+            // numbers are written as plain decimal strings so no precision is lost
+            let mut numbers = submit_data.nice_numbers.clone();
+            numbers.sort_by_key(|n| n.number);
+            let mut contents = String::from("number,num_uniques,base\n");
+            for n in numbers {
+                let _ = writeln!(contents, "{},{},{base}", n.number, n.num_uniques);
+            }
+            fs::write(path, contents).map_err(|e| e.to_string())?;
+
+            if let Some(distribution) = &submit_data.unique_distribution {
+                let mut contents = String::from("num_uniques,count\n");
+                for d in distribution {
+                    let _ = writeln!(contents, "{},{}", d.num_uniques, d.count);
+                }
+                fs::write(distribution_path(path), contents).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
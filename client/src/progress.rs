@@ -0,0 +1,54 @@
+//! Report processing speed and the estimated time left in a field.
+
+use std::time::{Duration, Instant};
+
+/// Tracks how far we are through a field and how fast we are going.
+pub struct Progress {
+    started: Instant,
+    range_size: u128,
+    /// Numbers already processed before this run, e.g. from a checkpoint.
+    already_done: u128,
+    /// Numbers processed so far in this run.
+    processed: u128,
+}
+
+/// Format a duration as hours, minutes, and seconds.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, s) => format!("{h}h {m:02}m {s:02}s"),
+    }
+}
+
+impl Progress {
+    pub fn new(range_size: u128, already_done: u128) -> Self {
+        Progress {
+            started: Instant::now(),
+            range_size,
+            already_done,
+            processed: 0,
+        }
+    }
+
+    /// Record a finished chunk and print the progress line.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn update(&mut self, chunk_size: u128) {
+        self.processed += chunk_size;
+        let done = self.already_done + self.processed;
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = self.processed as f64 / elapsed;
+        let remaining = self.range_size.saturating_sub(done) as f64 / rate;
+        let eta = if remaining.is_finite() {
+            format_duration(Duration::from_secs_f64(remaining))
+        } else {
+            "unknown".to_string()
+        };
+        println!(
+            "Progress: {:5.1}% ({done}/{}) | {rate:.3e} numbers/second | ETA {eta}",
+            done as f64 / self.range_size as f64 * 100.0,
+            self.range_size,
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Compare the plain and strided nice-only processors, and the residue filter lookups.
+//! Run with `cargo bench -p nice_common`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nice_common::base_range::get_base_range_u128;
+use nice_common::client_process::{process_range_niceonly, process_range_niceonly_strided};
+use nice_common::residue_filter::{get_residue_filter, ResidueFilter};
+
+fn bench_niceonly(c: &mut Criterion) {
+    for base in [40, 50] {
+        let range_start = get_base_range_u128(base).unwrap().unwrap().range_start;
+        let range_end = range_start + 1_000_000;
+
+        let mut group = c.benchmark_group(format!("niceonly_b{base}"));
+        group.bench_function("residue_filter", |b| {
+            b.iter(|| process_range_niceonly(black_box(range_start), range_end, base))
+        });
+        group.bench_function("stride_table", |b| {
+            b.iter(|| process_range_niceonly_strided(black_box(range_start), range_end, base))
+        });
+        group.finish();
+    }
+}
+
+/// Compare scanning the residue list against the lookup table in the hot loop.
+fn bench_residue_lookup(c: &mut Criterion) {
+    let base = 40;
+    let range_start = get_base_range_u128(base).unwrap().unwrap().range_start;
+    let range_end = range_start + 1_000_000;
+    let list = get_residue_filter(&base);
+    let filter = ResidueFilter::new(base);
+
+    let mut group = c.benchmark_group(format!("residue_lookup_b{base}"));
+    group.bench_function("list_contains", |b| {
+        b.iter(|| {
+            (black_box(range_start)..range_end)
+                .filter(|n| list.contains(&((n % (base as u128 - 1)) as u32)))
+                .count()
+        })
+    });
+    group.bench_function("table_allows", |b| {
+        b.iter(|| {
+            (black_box(range_start)..range_end)
+                .filter(|n| filter.allows(*n))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_niceonly, bench_residue_lookup);
+criterion_main!(benches);
@@ -24,6 +24,49 @@ pub fn get_base_range_natural(base: u32) -> Option<(Natural, Natural)> {
     }
 }
 
+/// Get the numbers whose `power` has exactly `digits` digits, as a half-open range.
+/// n^p < b^d exactly when n < ceil(b^d ^ 1/p), so both ends are ceiling roots.
+fn power_digits_range(base: &Natural, power: u64, digits: u64) -> (Natural, Natural) {
+    (
+        base.pow(digits - 1).ceiling_root(power),
+        base.pow(digits).ceiling_root(power),
+    )
+}
+
+/// Compute the range of numbers whose square and cube together have exactly `base` digits.
+/// Unlike `get_base_range_natural` this doesn't rely on a closed form for each residue mod 5.
+/// Instead it intersects the ranges for every split of the digits between the square and the
+/// cube. The digit count only grows with n, so the pieces join up into one range.
+/// Returns None if the range is empty or doesn't fit in a u128 (above base 97 or so).
+pub fn compute_base_range(base: u32) -> Option<FieldSize> {
+    if base < 2 {
+        return None;
+    }
+    let b = Natural::from(base);
+    let base_digits = u64::from(base);
+
+    let mut range: Option<(Natural, Natural)> = None;
+    for cube_digits in 1..base_digits {
+        let (square_start, square_end) = power_digits_range(&b, 2, base_digits - cube_digits);
+        let (cube_start, cube_end) = power_digits_range(&b, 3, cube_digits);
+        let start = square_start.max(cube_start);
+        let end = square_end.min(cube_end);
+        if start >= end {
+            continue;
+        }
+        range = Some(match range {
+            Some((range_start, range_end)) => (range_start.min(start), range_end.max(end)),
+            None => (start, end),
+        });
+    }
+
+    let (range_start, range_end) = range?;
+    Some(FieldSize::new(
+        u128::try_from(&range_start).ok()?,
+        u128::try_from(&range_end).ok()?,
+    ))
+}
+
 /// Get the range of possible values for a base, but return u128.
 /// Returns None if there are no valid numbers in that base.
 /// Returns Err if the numbers are too large for u128.
@@ -118,6 +161,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compute_base_range() {
+        assert_eq!(compute_base_range(5), Some(FieldSize::new(3, 5)));
+        assert_eq!(compute_base_range(6), None);
+        assert_eq!(compute_base_range(10), Some(FieldSize::new(47, 100)));
+        assert_eq!(compute_base_range(11), None);
+        assert_eq!(compute_base_range(200), None);
+        // this should agree with the closed form everywhere,
+        // except the closed form sometimes ends on the last valid number instead of after it
+        for base in 5..=97 {
+            let computed = compute_base_range(base);
+            match get_base_range_u128(base).unwrap() {
+                Some(closed) => {
+                    let computed = computed.unwrap();
+                    assert_eq!(computed.range_start, closed.range_start, "base {base}");
+                    assert!(
+                        computed.range_end.abs_diff(closed.range_end) <= 1,
+                        "base {base}: {computed:?} vs {closed:?}"
+                    );
+                }
+                None => assert_eq!(computed, None, "base {base}"),
+            }
+        }
+    }
+
     #[test]
     fn test_get_base_range_natural() {
         assert_eq!(
@@ -3,7 +3,8 @@
 use super::*;
 
 /// Different benchmark strategies.
-#[derive(Debug, Copy, Clone, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum BenchmarkMode {
     /// The default benchmark range: 1e5 @ base 40.
     Default,
@@ -14,6 +15,29 @@ pub enum BenchmarkMode {
     ExtraLarge,
     /// A benchmark range at a higher range: 1e5 @ base 80.
     HiBase,
+    /// A range the MSD filter can't skip any of in a detailed search: 1e5 @ base 50.
+    Base50WorstCase,
+    /// A range the MSD filter skips entirely: 1e5 @ base 50.
+    Base50BestCase,
+}
+
+/// The start of a base 50 range where every subrange might have a near miss.
+const BASE50_WORST_CASE_START: u128 = 26_511_541_950_332_782;
+
+/// The outcome of a single benchmark run, for tracking performance over time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchmarkResult {
+    pub benchmark: BenchmarkMode,
+    pub search_mode: SearchMode,
+    pub base: u32,
+    pub range_size: u128,
+    /// How many timed runs the stats are taken from.
+    pub iterations: u32,
+    /// The elapsed time and rate of the median run.
+    pub elapsed_secs: f64,
+    pub numbers_per_second: f64,
+    pub min_numbers_per_second: f64,
+    pub max_numbers_per_second: f64,
 }
 
 pub fn get_benchmark_field(mode: BenchmarkMode) -> DataToClient {
@@ -22,16 +46,25 @@ pub fn get_benchmark_field(mode: BenchmarkMode) -> DataToClient {
         BenchmarkMode::Large => 40,
         BenchmarkMode::ExtraLarge => 40,
         BenchmarkMode::HiBase => 80,
+        BenchmarkMode::Base50WorstCase => 50,
+        BenchmarkMode::Base50BestCase => 50,
+    };
+    let range_start = match mode {
+        BenchmarkMode::Base50WorstCase => BASE50_WORST_CASE_START,
+        _ => {
+            base_range::get_base_range_u128(base)
+                .unwrap()
+                .unwrap()
+                .range_start
+        }
     };
-    let range_start = base_range::get_base_range_u128(base)
-        .unwrap()
-        .unwrap()
-        .range_start;
     let range_size = match mode {
         BenchmarkMode::Default => 100000,
         BenchmarkMode::Large => 10000000,
         BenchmarkMode::ExtraLarge => 1000000000,
         BenchmarkMode::HiBase => 100000,
+        BenchmarkMode::Base50WorstCase => 100000,
+        BenchmarkMode::Base50BestCase => 100000,
     };
 
     DataToClient {
@@ -39,6 +72,8 @@ pub fn get_benchmark_field(mode: BenchmarkMode) -> DataToClient {
         base,
         range_start,
         range_end: range_start + range_size,
+        claim_expires_at: None,
+        server_time: None,
         range_size,
     }
 }
@@ -53,5 +88,33 @@ mod tests {
         get_benchmark_field(BenchmarkMode::Large);
         get_benchmark_field(BenchmarkMode::ExtraLarge);
         get_benchmark_field(BenchmarkMode::HiBase);
+        get_benchmark_field(BenchmarkMode::Base50WorstCase);
+        get_benchmark_field(BenchmarkMode::Base50BestCase);
+    }
+
+    #[test]
+    fn test_base50_msd_cases() {
+        let cutoff = get_near_miss_cutoff(50, None);
+
+        let worst = get_benchmark_field(BenchmarkMode::Base50WorstCase);
+        let kept = msd_prefix_filter::get_ranges_above_cutoff(
+            worst.range_start,
+            worst.range_end,
+            worst.base,
+            cutoff,
+        );
+        assert_eq!(
+            kept,
+            vec![FieldSize::new(worst.range_start, worst.range_end)]
+        );
+
+        let best = get_benchmark_field(BenchmarkMode::Base50BestCase);
+        let kept = msd_prefix_filter::get_ranges_above_cutoff(
+            best.range_start,
+            best.range_end,
+            best.base,
+            cutoff,
+        );
+        assert!(kept.is_empty());
     }
 }
@@ -1,55 +1,257 @@
 //! A module with client-server connection utilities.
 
 use super::*;
+use rand::Rng;
+use reqwest::blocking::Response;
+use reqwest::StatusCode;
+use std::thread;
+use std::time::Duration;
+
+/// Delay before the first retry, doubled on each following attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// The longest we will ever wait between attempts.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The JSON body the server sends with an error status.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ServerErrorBody {
+    /// A short, machine-readable name for the kind of error, like `unprocessable_entity`.
+    pub error: String,
+    pub message: String,
+    /// Why a submission failed validation, if that's what happened.
+    #[serde(default)]
+    pub reason: Option<serde_json::Value>,
+}
+
+/// Why a request to the server failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientApiError {
+    /// The request never got a response, like a timeout or a refused connection.
+    Network(String),
+    /// The server responded, but we couldn't read what it sent.
+    Deserialization(String),
+    /// The server responded with an error status, with anything it sent along.
+    /// The body is also parsed if the server sent a structured error.
+    ServerStatus {
+        status: u16,
+        body: String,
+        error: Option<ServerErrorBody>,
+    },
+}
+
+impl ClientApiError {
+    /// Whether the failure is transient (network error, 5xx, or 429) and worth trying again.
+    /// Anything else, like a bad claim, will not go away by retrying.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientApiError::Network(_) => true,
+            ClientApiError::Deserialization(_) => false,
+            ClientApiError::ServerStatus { status, .. } => {
+                *status >= 500 || *status == StatusCode::TOO_MANY_REQUESTS.as_u16()
+            }
+        }
+    }
+
+    /// Whether the server read the request and refused it, like a submission that failed validation.
+    /// Sending the same data again will get the same answer.
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, ClientApiError::ServerStatus { status, .. }
+            if *status == StatusCode::UNPROCESSABLE_ENTITY.as_u16())
+    }
+}
+
+impl std::fmt::Display for ClientApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientApiError::Network(e) => write!(f, "Network error: {e}"),
+            ClientApiError::Deserialization(e) => {
+                write!(f, "Failed to read the server's response: {e}")
+            }
+            ClientApiError::ServerStatus {
+                status,
+                error: Some(error),
+                ..
+            } => write!(
+                f,
+                "Server returned {status} ({}): {}",
+                error.error, error.message
+            ),
+            ClientApiError::ServerStatus { status, body, .. } => {
+                write!(f, "Server returned {status}: {body}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientApiError {}
+
+/// Turn an error status into an error, including anything we got from the server.
+fn check_response(response: Result<Response, reqwest::Error>) -> Result<Response, ClientApiError> {
+    let response = response.map_err(|e| ClientApiError::Network(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let body = response.text().unwrap_or_default();
+        Err(ClientApiError::ServerStatus {
+            status: status.as_u16(),
+            error: serde_json::from_str(&body).ok(),
+            body,
+        })
+    }
+}
+
+/// Run a request, retrying transient failures with exponential backoff and jitter.
+/// Gives up after `max_retries` retries and returns the last error.
+fn with_retries<T>(
+    max_retries: u32,
+    mut request: impl FnMut() -> Result<T, ClientApiError>,
+) -> Result<T, ClientApiError> {
+    let mut rng = rand::thread_rng();
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Ok(result) => return Ok(result),
+            Err(e) if !e.is_retryable() => return Err(e),
+            Err(e) if attempt >= max_retries => {
+                println!("{e}, giving up after {attempt} retries.");
+                return Err(e);
+            }
+            Err(e) => {
+                let backoff = RETRY_BASE_DELAY
+                    .saturating_mul(2u32.saturating_pow(attempt))
+                    .min(RETRY_MAX_DELAY);
+                let delay = backoff.mul_f64(rng.gen_range(0.5..=1.0));
+                println!("{e}, retrying in {delay:.1?}...");
+                thread::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
 
 /// Request a field from the server. Supplies CLI options as query strings.
-pub fn get_field_from_server(mode: &SearchMode, api_base: &str) -> DataToClient {
+pub fn get_field_from_server(
+    mode: &SearchMode,
+    api_base: &str,
+    only_base: Option<u32>,
+    max_retries: u32,
+) -> Result<DataToClient, ClientApiError> {
     // build the url
-    let url = match mode {
+    let mut url = match mode {
         SearchMode::Detailed => format!("{api_base}/claim/detailed"),
         SearchMode::Niceonly => format!("{api_base}/claim/niceonly"),
+        SearchMode::NearMiss => format!("{api_base}/claim/nearmiss"),
     };
+    if let Some(base) = only_base {
+        url.push_str(&format!("?base={base}"));
+    }
 
-    // send it
-    let response = reqwest::blocking::get(url).unwrap_or_else(|e| panic!("Error: {}", e));
+    // send it, then deserialize
+    with_retries(max_retries, || {
+        check_response(reqwest::blocking::get(&url))?
+            .json::<DataToClient>()
+            .map_err(|e| ClientApiError::Deserialization(e.to_string()))
+    })
+}
 
-    // deserialize and unwrap or panic
-    match response.json::<DataToClient>() {
-        Ok(claim_data) => claim_data,
-        Err(e) => panic!("Error: {}", e),
-    }
+/// Post field results to one of the submit endpoints. Returns the server's response message.
+fn post_results(
+    url: &str,
+    submit_data: &DataToServer,
+    max_retries: u32,
+) -> Result<String, ClientApiError> {
+    let client = reqwest::blocking::Client::new();
+
+    // send it, then read the response
+    with_retries(max_retries, || {
+        check_response(client.post(url).json(submit_data).send())?
+            .text()
+            .map_err(|e| ClientApiError::Deserialization(e.to_string()))
+    })
 }
 
-/// Submit field results to the server. Panic if there is an error.
-pub fn submit_field_to_server(api_base: &str, submit_data: DataToServer) {
-    // build the url
-    let url = format!("{api_base}/submit");
-
-    // send it
-    let response = reqwest::blocking::Client::new()
-        .post(url)
-        .json(&submit_data)
-        .send();
-
-    // check for network errors
-    match response {
-        Ok(response) => {
-            // check for server errors
-            if response.status().is_success() {
-                match response.text() {
-                    Ok(msg) => println!("Server response: {}", msg), // TODO: silence this or return as string
-                    Err(e) => panic!("Server returned success but an error occured: {}", e),
-                }
-            } else {
-                match response.text() {
-                    // we probably did something wrong, print anything we got from the server
-                    Ok(msg) => panic!("Server returned an error: {}", msg),
-                    Err(e) => panic!("Server returned an error, but another error occured: {}", e),
-                }
-            }
-        }
-        Err(e) => panic!("Network error: {}", e),
-    }
+/// Submit field results to the server. Returns the server's response message.
+pub fn submit_field_to_server(
+    api_base: &str,
+    submit_data: &DataToServer,
+    max_retries: u32,
+) -> Result<String, ClientApiError> {
+    post_results(&format!("{api_base}/submit"), submit_data, max_retries)
+}
+
+/// Have the server check field results without saving them. Returns the server's response message.
+pub fn validate_field_with_server(
+    api_base: &str,
+    submit_data: &DataToServer,
+    max_retries: u32,
+) -> Result<String, ClientApiError> {
+    post_results(
+        &format!("{api_base}/submit/validate"),
+        submit_data,
+        max_retries,
+    )
 }
 
-// TODO: add tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_api_error_is_retryable() {
+        let status = |status| ClientApiError::ServerStatus {
+            status,
+            body: String::new(),
+            error: None,
+        };
+        assert!(ClientApiError::Network("timed out".to_string()).is_retryable());
+        assert!(status(500).is_retryable());
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert!(!status(422).is_retryable());
+        assert!(!ClientApiError::Deserialization("bad json".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn test_submit_rejected() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        // a server that rejects one submission, like the api does when validation fails
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).unwrap();
+            let body = r#"{"error":"unprocessable_entity","message":"Number 69 was submitted more than once.","reason":{"kind":"duplicate_number","number":69}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 422 Unprocessable Entity\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+
+        let submit_data = DataToServer {
+            claim_id: 0,
+            username: "test".to_string(),
+            client_version: CLIENT_VERSION.to_string(),
+            unique_distribution: None,
+            nice_numbers: Vec::new(),
+        };
+        let err = submit_field_to_server(&api_base, &submit_data, 0).unwrap_err();
+        server.join().unwrap();
+
+        assert!(err.is_rejection());
+        assert!(!err.is_retryable());
+        let ClientApiError::ServerStatus { status, error, .. } = err else {
+            panic!("expected a server status error, got {err:?}");
+        };
+        assert_eq!(status, 422);
+        let error = error.unwrap();
+        assert_eq!(error.error, "unprocessable_entity");
+        assert_eq!(error.message, "Number 69 was submitted more than once.");
+    }
+}
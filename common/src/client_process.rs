@@ -42,14 +42,39 @@ pub fn get_num_unique_digits(num_u128: u128, base: u32) -> u32 {
 }
 
 /// Process a field by aggregating statistics on the niceness of numbers in a range.
-pub fn process_detailed(claim_data: &DataToClient, username: &String) -> DataToServer {
-    // get the basic parameters
-    let base = claim_data.base;
-    let range_start = claim_data.range_start;
-    let range_end = claim_data.range_end;
+/// The near-miss cutoff defaults to `NEAR_MISS_CUTOFF_PERCENT`.
+pub fn process_detailed(
+    claim_data: &DataToClient,
+    username: &String,
+    near_miss_cutoff: Option<f32>,
+) -> DataToServer {
+    let results = process_range_detailed(
+        claim_data.range_start,
+        claim_data.range_end,
+        claim_data.base,
+        near_miss_cutoff,
+    );
 
-    // get the minimum cutoff (90% of the base)
-    let nice_list_cutoff = (base as f32 * NEAR_MISS_CUTOFF_PERCENT) as u32;
+    DataToServer {
+        claim_id: claim_data.claim_id,
+        username: username.to_owned(),
+        client_version: CLIENT_VERSION.to_string(),
+        unique_distribution: Some(results.distribution),
+        nice_numbers: results.nice_numbers,
+    }
+}
+
+/// Aggregate statistics on the niceness of numbers in an arbitrary range.
+/// The range does not need to be a whole field, so results can be combined later.
+/// The near-miss cutoff defaults to `NEAR_MISS_CUTOFF_PERCENT`.
+pub fn process_range_detailed(
+    range_start: u128,
+    range_end: u128,
+    base: u32,
+    near_miss_cutoff: Option<f32>,
+) -> FieldResults {
+    // get the minimum cutoff (90% of the base by default)
+    let nice_list_cutoff = get_near_miss_cutoff(base, near_miss_cutoff);
 
     // init the output maps
     let mut unique_distribution: HashMap<u32, u128> = (1..=base).map(|i| (i, 0u128)).collect();
@@ -90,12 +115,10 @@ pub fn process_detailed(claim_data: &DataToClient, username: &String) -> DataToS
         })
         .collect();
 
-    DataToServer {
-        claim_id: claim_data.claim_id,
-        username: username.to_owned(),
-        client_version: CLIENT_VERSION.to_string(),
-        unique_distribution: Some(submit_distribution),
+    FieldResults {
+        distribution: submit_distribution,
         nice_numbers: submit_numbers,
+        processed_count: range_end - range_start,
     }
 }
 
@@ -139,13 +162,30 @@ pub fn get_is_nice(num: u128, base: u32) -> bool {
 /// Process a field by looking for completely nice numbers.
 /// Implements several optimizations over the detailed search.
 pub fn process_niceonly(claim_data: &DataToClient, username: &String) -> DataToServer {
-    let base = claim_data.base;
-    let range_start = claim_data.range_start;
-    let range_end = claim_data.range_end;
-    let residue_filter = residue_filter::get_residue_filter(&base);
+    let results = process_range_niceonly_strided(
+        claim_data.range_start,
+        claim_data.range_end,
+        claim_data.base,
+    );
 
-    let nice_list = (range_start..range_end)
-        .filter(|num| residue_filter.contains(&((num % (base as u128 - 1)) as u32)))
+    DataToServer {
+        claim_id: claim_data.claim_id,
+        username: username.to_owned(),
+        client_version: CLIENT_VERSION.to_string(),
+        unique_distribution: None,
+        nice_numbers: results.nice_numbers,
+    }
+}
+
+/// Look for completely nice numbers in an arbitrary range.
+/// The distribution in the results is always empty.
+pub fn process_range_niceonly(range_start: u128, range_end: u128, base: u32) -> FieldResults {
+    let residue_filter = residue_filter::ResidueFilter::new(base);
+
+    let mut processed_count = 0;
+    let nice_numbers = (range_start..range_end)
+        .filter(|num| residue_filter.allows(*num))
+        .inspect(|_| processed_count += 1)
         .filter(|num| get_is_nice(*num, base))
         .map(|number| NiceNumberSimple {
             number,
@@ -153,12 +193,127 @@ pub fn process_niceonly(claim_data: &DataToClient, username: &String) -> DataToS
         })
         .collect();
 
+    FieldResults {
+        distribution: Vec::new(),
+        nice_numbers,
+        processed_count,
+    }
+}
+
+/// Look for completely nice numbers like `process_range_niceonly`, but step directly
+/// between candidates with a stride table instead of checking every number.
+pub fn process_range_niceonly_strided(
+    range_start: u128,
+    range_end: u128,
+    base: u32,
+) -> FieldResults {
+    let stride_table = stride_filter::StrideTable::cached(base, stride_filter::STRIDE_LSD_DIGITS);
+
+    let mut processed_count = 0;
+    let nice_numbers = stride_table
+        .iter(range_start, range_end)
+        .inspect(|_| processed_count += 1)
+        .filter(|num| get_is_nice(*num, base))
+        .map(|number| NiceNumberSimple {
+            number,
+            num_uniques: base,
+        })
+        .collect();
+
+    FieldResults {
+        distribution: Vec::new(),
+        nice_numbers,
+        processed_count,
+    }
+}
+
+/// Count the completely nice numbers in a range without collecting them.
+pub fn process_range_count_only(range_start: u128, range_end: u128, base: u32) -> u64 {
+    let stride_table = stride_filter::StrideTable::cached(base, stride_filter::STRIDE_LSD_DIGITS);
+
+    stride_table
+        .iter(range_start, range_end)
+        .filter(|num| get_is_nice(*num, base))
+        .count() as u64
+}
+
+/// Look for completely nice numbers like `process_range_niceonly`, but first skip any
+/// subranges where the most significant digits already rule out a nice number.
+pub fn process_range_niceonly_msd(range_start: u128, range_end: u128, base: u32) -> FieldResults {
+    let residue_filter = residue_filter::ResidueFilter::new(base);
+
+    let mut processed_count = 0;
+    let nice_numbers = msd_prefix_filter::get_valid_ranges(range_start, range_end, base)
+        .into_iter()
+        .flat_map(|range| range.iter())
+        .filter(|num| residue_filter.allows(*num))
+        .inspect(|_| processed_count += 1)
+        .filter(|num| get_is_nice(*num, base))
+        .map(|number| NiceNumberSimple {
+            number,
+            num_uniques: base,
+        })
+        .collect();
+
+    FieldResults {
+        distribution: Vec::new(),
+        nice_numbers,
+        processed_count,
+    }
+}
+
+/// Process a field by looking for numbers that are nice or nearly nice.
+pub fn process_near_miss(claim_data: &DataToClient, username: &String) -> DataToServer {
+    let results = process_range_near_miss(
+        claim_data.range_start,
+        claim_data.range_end,
+        claim_data.base,
+        NEAR_MISS_DISTANCE,
+    );
+
     DataToServer {
         claim_id: claim_data.claim_id,
         username: username.to_owned(),
         client_version: CLIENT_VERSION.to_string(),
         unique_distribution: None,
-        nice_numbers: nice_list,
+        nice_numbers: results.nice_numbers,
+    }
+}
+
+/// Collect every number in an arbitrary range with at least (base - distance) unique digits.
+/// Skips subranges that can't reach the cutoff, so the distribution in the results is always empty.
+pub fn process_range_near_miss(
+    range_start: u128,
+    range_end: u128,
+    base: u32,
+    distance: u32,
+) -> FieldResults {
+    let min_uniques = base.saturating_sub(distance);
+
+    let valid_ranges = msd_prefix_filter::get_ranges_above_cutoff(
+        range_start,
+        range_end,
+        base,
+        min_uniques.saturating_sub(1),
+    );
+    let processed_count = valid_ranges.iter().map(|range| range.range_size).sum();
+
+    let nice_numbers = valid_ranges
+        .into_iter()
+        .flat_map(|range| range.range_start..range.range_end)
+        .filter_map(|number| {
+            let num_uniques = get_num_unique_digits(number, base);
+            (num_uniques >= min_uniques).then_some(NiceNumberSimple {
+                number,
+                num_uniques,
+            })
+        })
+        .collect();
+
+    FieldResults {
+        distribution: Vec::new(),
+        nice_numbers,
+        processed_count,
     }
 }
 
@@ -175,6 +330,8 @@ mod tests {
             range_start: 47,
             range_end: 100,
             range_size: 53,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -227,7 +384,7 @@ mod tests {
                 num_uniques: 10,
             }]),
         };
-        assert_eq!(process_detailed(&claim_data, &username), submit_data);
+        assert_eq!(process_detailed(&claim_data, &username, None), submit_data);
     }
 
     #[test]
@@ -239,6 +396,8 @@ mod tests {
             range_start: 916284264916,
             range_end: 916284264916 + 10000,
             range_size: 10000,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -408,7 +567,7 @@ mod tests {
             ])),
             nice_numbers: Vec::new(),
         };
-        assert_eq!(process_detailed(&claim_data, &username), submit_data);
+        assert_eq!(process_detailed(&claim_data, &username, None), submit_data);
     }
 
     #[test]
@@ -420,6 +579,8 @@ mod tests {
             range_start: 653245554420798943087177909799,
             range_end: 653245554420798943087177909799 + 10000,
             range_size: 10000,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -749,7 +910,19 @@ mod tests {
             ])),
             nice_numbers: Vec::new(),
         };
-        assert_eq!(process_detailed(&claim_data, &username), submit_data);
+        assert_eq!(process_detailed(&claim_data, &username, None), submit_data);
+    }
+
+    #[test]
+    fn process_range_detailed_custom_cutoff() {
+        let range_start = 47;
+        let range_end = 100;
+        let standard = process_range_detailed(range_start, range_end, 10, None);
+        let loose = process_range_detailed(range_start, range_end, 10, Some(0.7));
+        assert_eq!(standard.distribution, loose.distribution);
+        assert!(standard.nice_numbers.iter().all(|n| n.num_uniques > 9));
+        assert!(loose.nice_numbers.iter().all(|n| n.num_uniques > 7));
+        assert!(loose.nice_numbers.len() > standard.nice_numbers.len());
     }
 
     #[test]
@@ -761,6 +934,8 @@ mod tests {
             range_start: 47,
             range_end: 100,
             range_size: 53,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -784,6 +959,8 @@ mod tests {
             range_start: 916284264916,
             range_end: 916284264916 + 10000,
             range_size: 10000,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -804,6 +981,8 @@ mod tests {
             range_start: 653245554420798943087177909799,
             range_end: 653245554420798943087177909799 + 10000,
             range_size: 10000,
+            claim_expires_at: None,
+            server_time: None,
         };
         let submit_data = DataToServer {
             claim_id: claim_data.claim_id,
@@ -814,4 +993,158 @@ mod tests {
         };
         assert_eq!(process_niceonly(&claim_data, &username), submit_data);
     }
+
+    #[test]
+    fn process_range_niceonly_strided_matches() {
+        // base 10 is the only base with a nice number small enough to find in a test
+        let range_start = 47;
+        let range_end = 100;
+        let expected = process_range_niceonly(range_start, range_end, 10).nice_numbers;
+        assert!(!expected.is_empty());
+        assert_eq!(
+            process_range_niceonly_strided(range_start, range_end, 10).nice_numbers,
+            expected
+        );
+
+        // elsewhere there is nothing to find, so check every number the stride skips instead
+        for base in [40, 50] {
+            let range_start = base_range::get_base_range_u128(base)
+                .unwrap()
+                .unwrap()
+                .range_start;
+            let range_end = range_start + 10_000;
+            let residue_filter = residue_filter::ResidueFilter::new(base);
+            let stride_table =
+                stride_filter::StrideTable::cached(base, stride_filter::STRIDE_LSD_DIGITS);
+            let strided: Vec<u128> = stride_table.iter(range_start, range_end).collect();
+            let skipped: Vec<u128> = (range_start..range_end)
+                .filter(|num| residue_filter.allows(*num))
+                .filter(|num| strided.binary_search(num).is_err())
+                .collect();
+            assert!(!strided.is_empty());
+            assert!(!skipped.is_empty());
+            assert!(strided.iter().all(|num| residue_filter.allows(*num)));
+
+            // a skipped number must repeat a digit in the low digits of its square or cube
+            for num in skipped {
+                let num = Natural::from(num);
+                let squared = (&num).pow(2);
+                let cubed = &squared * &num;
+                let mut low_digits: Vec<u32> = [squared, cubed]
+                    .iter()
+                    .flat_map(|n| {
+                        n.to_digits_asc(&base)
+                            .into_iter()
+                            .take(stride_filter::STRIDE_LSD_DIGITS as usize)
+                    })
+                    .collect();
+                let num_digits = low_digits.len();
+                low_digits.sort_unstable();
+                low_digits.dedup();
+                assert!(low_digits.len() < num_digits, "{num} was skipped");
+            }
+        }
+    }
+
+    #[test]
+    fn process_range_count_only_matches() {
+        for base in [10, 40] {
+            let base_range = base_range::get_base_range_u128(base).unwrap().unwrap();
+            let claim_data = DataToClient {
+                claim_id: 0,
+                base,
+                range_start: base_range.range_start,
+                range_end: base_range.range_end.min(base_range.range_start + 100_000),
+                range_size: 0,
+                claim_expires_at: None,
+                server_time: None,
+            };
+            let expected = process_niceonly(&claim_data, &"anonymous".to_string())
+                .nice_numbers
+                .len() as u64;
+            assert_eq!(
+                process_range_count_only(claim_data.range_start, claim_data.range_end, base),
+                expected
+            );
+        }
+        assert_eq!(process_range_count_only(47, 100, 10), 1);
+    }
+
+    #[test]
+    fn process_range_near_miss_matches_detailed() {
+        for base in [10, 40] {
+            let base_range = base_range::get_base_range_u128(base).unwrap().unwrap();
+            let range_start = base_range.range_start;
+            let range_end = base_range.range_end.min(range_start + 10_000);
+            let min_uniques = base - NEAR_MISS_DISTANCE;
+            let expected: Vec<NiceNumberSimple> = (range_start..range_end)
+                .map(|number| NiceNumberSimple {
+                    number,
+                    num_uniques: get_num_unique_digits(number, base),
+                })
+                .filter(|n| n.num_uniques >= min_uniques)
+                .collect();
+            assert_eq!(
+                process_range_near_miss(range_start, range_end, base, NEAR_MISS_DISTANCE)
+                    .nice_numbers,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn process_range_niceonly_msd_matches() {
+        // base 10 is the only base with a nice number small enough to find in a test
+        let range_start = 47;
+        let range_end = 100;
+        let expected = process_range_niceonly(range_start, range_end, 10).nice_numbers;
+        assert!(!expected.is_empty());
+        assert_eq!(
+            process_range_niceonly_msd(range_start, range_end, 10).nice_numbers,
+            expected
+        );
+
+        // the filter drops part of this window, so check every number it drops
+        let base = 22;
+        let range_start = 234256;
+        let range_end = range_start + 100_000;
+        let msd = process_range_niceonly_msd(range_start, range_end, base);
+        let expected = process_range_niceonly(range_start, range_end, base);
+        assert!(msd.processed_count < expected.processed_count);
+        assert_eq!(msd.nice_numbers, expected.nice_numbers);
+        let valid_ranges = msd_prefix_filter::get_valid_ranges(range_start, range_end, base);
+        let dropped = (range_start..range_end)
+            .filter(|num| !valid_ranges.iter().any(|r| r.contains(*num)))
+            .inspect(|num| assert!(get_num_unique_digits(*num, base) < base))
+            .count();
+        assert!(dropped > 0);
+
+        // with a lower cutoff there are numbers to find, and the filter keeps all of them
+        let cutoff = base - 3;
+        let near_misses: Vec<u128> = (range_start..range_end)
+            .filter(|num| get_num_unique_digits(*num, base) > cutoff)
+            .collect();
+        assert!(!near_misses.is_empty());
+        let valid_ranges =
+            msd_prefix_filter::get_ranges_above_cutoff(range_start, range_end, base, cutoff);
+        let valid_size: u128 = valid_ranges.iter().map(|r| r.range_size).sum();
+        assert!(valid_size < range_end - range_start);
+        assert!(near_misses
+            .iter()
+            .all(|num| valid_ranges.iter().any(|r| r.contains(*num))));
+    }
+
+    #[test]
+    fn process_range_niceonly_msd_skippable() {
+        // the whole range is ruled out by its leading digits
+        let range_start = 26507984537059635;
+        let range_end = range_start + 10_000;
+        assert!(msd_prefix_filter::get_valid_ranges(range_start, range_end, 50).is_empty());
+        let msd = process_range_niceonly_msd(range_start, range_end, 50);
+        assert_eq!(
+            msd.nice_numbers,
+            process_range_niceonly(range_start, range_end, 50).nice_numbers
+        );
+        assert_eq!(msd.processed_count, 0);
+    }
 }
@@ -2,26 +2,106 @@
 
 use super::*;
 
+/// The rules for promoting a field to consensus (CL3 or above).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsensusConfig {
+    /// How many submissions must agree with each other.
+    pub agreeing_submissions: usize,
+    /// How many detailed submissions the field needs in total, agreeing or not.
+    pub minimum_submissions: usize,
+    /// Client versions known to produce bad results. A trailing `*` matches any suffix.
+    pub disqualified_versions: Vec<String>,
+}
+
+impl ConsensusConfig {
+    /// Whether submissions from this client version should be thrown out.
+    pub fn is_disqualified_version(&self, client_version: &str) -> bool {
+        self.disqualified_versions
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => client_version.starts_with(prefix),
+                None => client_version == pattern,
+            })
+    }
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        ConsensusConfig {
+            agreeing_submissions: CONSENSUS_AGREEING_SUBMISSIONS,
+            minimum_submissions: CONSENSUS_MINIMUM_SUBMISSIONS,
+            disqualified_versions: Vec::new(),
+        }
+    }
+}
+
+/// A group of submissions that agree with each other but not with the majority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsensusConflict {
+    pub submission_ids: Vec<u128>,
+    pub distribution_differs: bool,
+    pub numbers_differ: bool,
+}
+
+/// Every group of submissions for a field that disagrees with the majority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisagreementReport {
+    pub field_id: u128,
+    pub majority_submission_ids: Vec<u128>,
+    pub conflicts: Vec<ConsensusConflict>,
+}
+
+/// What consensus was reached for a field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsensusOutcome {
+    /// The submission to treat as canon, or None if there are no submissions to count.
+    pub canon_submission: Option<SubmissionRecord>,
+    pub check_level: u8,
+    /// Every group of submissions that disagrees with the majority, if there are any.
+    pub disagreement: Option<DisagreementReport>,
+    /// Submissions from disqualified client versions, which were not counted.
+    pub disqualified_ids: Vec<u128>,
+}
+
 /// Given a field and submissions, determine if there is a consensus.
 /// If so, update the canon submission ID and field check level.
+/// Also reports any submissions that disagree with the majority,
+/// and the IDs of any submissions from disqualified client versions, which are not counted.
 pub fn evaluate_consensus(
     field: &FieldRecord,
-    submissions: &Vec<SubmissionRecord>,
-) -> Result<(Option<SubmissionRecord>, u8), String> {
+    submissions: &[SubmissionRecord],
+    config: &ConsensusConfig,
+) -> Result<ConsensusOutcome, String> {
+    // Set aside submissions from known-bad clients so they don't count toward agreement
+    let (disqualified, submissions): (Vec<&SubmissionRecord>, Vec<&SubmissionRecord>) = submissions
+        .iter()
+        .partition(|sub| config.is_disqualified_version(&sub.client_version));
+    let disqualified_ids: Vec<u128> = disqualified.iter().map(|s| s.submission_id).collect();
+
     // If there are no submissions, reset the canon submission and cap the check level
     if submissions.is_empty() {
-        return Ok((None, field.check_level.min(1)));
+        return Ok(ConsensusOutcome {
+            canon_submission: None,
+            check_level: field.check_level.min(1),
+            disagreement: None,
+            disqualified_ids,
+        });
     }
     // If there is one submission, return it
     if submissions.len() == 1 {
         if let Some(sub) = submissions.first() {
-            return Ok((Some(sub.clone()), 2));
+            return Ok(ConsensusOutcome {
+                canon_submission: Some((*sub).clone()),
+                check_level: 2,
+                disagreement: None,
+                disqualified_ids,
+            });
         }
     }
 
     // Group submissions by distribution and numbers
     let mut submission_groups: HashMap<SubmissionCandidate, Vec<SubmissionRecord>> = HashMap::new();
-    for sub in submissions {
+    for sub in &submissions {
         let sub_distribution = sub.distribution.clone().ok_or_else(|| {
             format!(
                 "No distribution found in detailed submission #{}",
@@ -39,21 +119,20 @@ pub fn evaluate_consensus(
         submission_groups
             .entry(subcan)
             .or_default()
-            .push(sub.clone());
+            .push((*sub).clone());
     }
 
     // Find the group with the highest number of submissions
     // Note this does not handle ties, they are resolved effectively at random
-    let majority_group = submission_groups
-        .values()
-        .max_by_key(|v| v.len())
+    let (majority_candidate, majority_group) = submission_groups
+        .iter()
+        .max_by_key(|(_, v)| v.len())
         .ok_or_else(|| {
             format!(
                 "Could not get majority group from submission_groups: {:?}.",
                 submission_groups
             )
-        })?
-        .clone();
+        })?;
 
     // Get the first submission inside the agreeing group
     let first_submission = majority_group
@@ -61,8 +140,179 @@ pub fn evaluate_consensus(
         .min_by_key(|sub| sub.submit_time)
         .ok_or_else(|| format!("No submission in majority_group: {:?}.", majority_group))?;
 
-    // Determine the check level
-    let check_level = (majority_group.len().min(u8::MAX as usize) + 1) as u8;
+    // Determine the check level, only counting as consensus if enough submissions agree
+    let check_level = if majority_group.len() >= config.agreeing_submissions
+        && submissions.len() >= config.minimum_submissions
+    {
+        (majority_group.len().min(u8::MAX as usize - 1) + 1).max(3) as u8
+    } else {
+        2
+    };
+
+    // Note how every other group differs from the majority
+    let mut conflicts: Vec<ConsensusConflict> = submission_groups
+        .iter()
+        .filter(|(candidate, _)| *candidate != majority_candidate)
+        .map(|(candidate, group)| {
+            let mut submission_ids: Vec<u128> = group.iter().map(|s| s.submission_id).collect();
+            submission_ids.sort_unstable();
+            ConsensusConflict {
+                submission_ids,
+                distribution_differs: candidate.distribution != majority_candidate.distribution,
+                numbers_differ: candidate.numbers != majority_candidate.numbers,
+            }
+        })
+        .collect();
+    conflicts.sort_by_key(|c| c.submission_ids.first().copied());
+    let disagreement = if conflicts.is_empty() {
+        None
+    } else {
+        let mut majority_submission_ids: Vec<u128> =
+            majority_group.iter().map(|s| s.submission_id).collect();
+        majority_submission_ids.sort_unstable();
+        Some(DisagreementReport {
+            field_id: field.field_id,
+            majority_submission_ids,
+            conflicts,
+        })
+    };
+
+    Ok(ConsensusOutcome {
+        canon_submission: Some(first_submission.clone()),
+        check_level,
+        disagreement,
+        disqualified_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field() -> FieldRecord {
+        FieldRecord {
+            field_id: 1,
+            base: 10,
+            chunk_id: None,
+            range_start: 47,
+            range_end: 100,
+            range_size: 53,
+            last_claim_time: None,
+            canon_submission_id: None,
+            check_level: 2,
+            prioritize: false,
+            canon_updated_at: None,
+        }
+    }
+
+    fn submission(submission_id: u128, num_uniques: u32) -> SubmissionRecord {
+        SubmissionRecord {
+            submission_id,
+            claim_id: submission_id,
+            field_id: 1,
+            search_mode: SearchMode::Detailed,
+            submit_time: Utc::now(),
+            elapsed_secs: 1.0,
+            username: "anonymous".to_string(),
+            user_ip: "unknown".to_string(),
+            client_version: CLIENT_VERSION.to_string(),
+            disqualified: false,
+            suspicious: false,
+            distribution: Some(distribution_stats::expand_distribution(
+                &[UniquesDistributionSimple {
+                    num_uniques,
+                    count: 53,
+                }],
+                10,
+            )),
+            numbers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn consensus_default_config() {
+        let config = ConsensusConfig::default();
+        let outcome = evaluate_consensus(&field(), &[submission(1, 7)], &config).unwrap();
+        assert_eq!(outcome.canon_submission.map(|s| s.submission_id), Some(1));
+        assert_eq!(outcome.check_level, 2);
+
+        let submissions = vec![submission(1, 7), submission(2, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.check_level, 3);
+    }
+
+    #[test]
+    fn consensus_custom_config() {
+        let config = ConsensusConfig {
+            agreeing_submissions: 3,
+            minimum_submissions: 4,
+            ..ConsensusConfig::default()
+        };
+        let submissions = vec![submission(1, 7), submission(2, 7), submission(3, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.check_level, 2);
 
-    Ok((Some(first_submission.clone()), check_level))
+        let submissions = vec![
+            submission(1, 7),
+            submission(2, 7),
+            submission(3, 7),
+            submission(4, 8),
+        ];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.canon_submission.map(|s| s.submission_id), Some(1));
+        assert_eq!(outcome.check_level, 4);
+    }
+
+    #[test]
+    fn consensus_disagreement_report() {
+        let config = ConsensusConfig::default();
+        let submissions = vec![submission(1, 7), submission(2, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.disagreement, None);
+
+        let submissions = vec![submission(1, 7), submission(2, 8), submission(3, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(
+            outcome.disagreement,
+            Some(DisagreementReport {
+                field_id: 1,
+                majority_submission_ids: vec![1, 3],
+                conflicts: vec![ConsensusConflict {
+                    submission_ids: vec![2],
+                    distribution_differs: true,
+                    numbers_differ: false,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn consensus_disqualified_versions() {
+        let config = ConsensusConfig {
+            disqualified_versions: vec!["2.9.*".to_string()],
+            ..ConsensusConfig::default()
+        };
+        assert!(config.is_disqualified_version("2.9.1"));
+        assert!(!config.is_disqualified_version(CLIENT_VERSION));
+
+        let poisoned = |id| SubmissionRecord {
+            client_version: "2.9.1".to_string(),
+            ..submission(id, 8)
+        };
+
+        // the poisoned submission is outvoted and left out of the disagreement report
+        let submissions = vec![submission(1, 7), poisoned(2), submission(3, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.canon_submission.map(|s| s.submission_id), Some(1));
+        assert_eq!(outcome.check_level, 3);
+        assert_eq!(outcome.disagreement, None);
+        assert_eq!(outcome.disqualified_ids, vec![2]);
+
+        // even when poisoned submissions are the majority they can't win
+        let submissions = vec![poisoned(1), poisoned(2), submission(3, 7)];
+        let outcome = evaluate_consensus(&field(), &submissions, &config).unwrap();
+        assert_eq!(outcome.canon_submission.map(|s| s.submission_id), Some(3));
+        assert_eq!(outcome.check_level, 2);
+        assert_eq!(outcome.disqualified_ids, vec![1, 2]);
+    }
 }
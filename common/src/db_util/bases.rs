@@ -115,6 +115,23 @@ pub fn get_base_by_id(conn: &mut PgConnection, row_id: u32) -> Result<BaseRecord
         .and_then(private_to_public)
 }
 
+pub fn try_get_base_by_id(
+    conn: &mut PgConnection,
+    row_id: u32,
+) -> Result<Option<BaseRecord>, String> {
+    use self::bases::dsl::*;
+
+    let row_id = conversions::u32_to_i32(row_id)?;
+
+    bases
+        .filter(id.eq(row_id))
+        .first::<BasePrivate>(conn)
+        .optional()
+        .map_err(|err| err.to_string())?
+        .map(private_to_public)
+        .transpose()
+}
+
 pub fn get_all_bases(conn: &mut PgConnection) -> Result<Vec<BaseRecord>, String> {
     use self::bases::dsl::*;
 
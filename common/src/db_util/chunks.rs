@@ -18,6 +18,7 @@ table! {
         niceness_stdev -> Nullable<Float>,
         distribution -> Jsonb,
         numbers -> Jsonb,
+        last_downsampled_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -36,6 +37,7 @@ struct ChunkPrivate {
     niceness_stdev: Option<f32>,
     distribution: Value,
     numbers: Value,
+    last_downsampled_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Insertable)]
@@ -62,6 +64,7 @@ fn private_to_public(p: ChunkPrivate) -> Result<ChunkRecord, String> {
         niceness_stdev: p.niceness_stdev,
         distribution: deserialize_distribution(p.distribution)?,
         numbers: deserialize_numbers(p.numbers)?,
+        last_downsampled_at: p.last_downsampled_at,
     })
 }
 
@@ -80,6 +83,7 @@ fn public_to_private(p: ChunkRecord) -> Result<ChunkPrivate, String> {
         niceness_stdev: p.niceness_stdev,
         distribution: serialize_distribution(p.distribution)?,
         numbers: serialize_numbers(p.numbers)?,
+        last_downsampled_at: p.last_downsampled_at,
     })
 }
 
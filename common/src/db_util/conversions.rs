@@ -130,6 +130,7 @@ pub fn deserialize_searchmode(i: String) -> Result<SearchMode, String> {
     match i.as_str() {
         "detailed" => Ok(SearchMode::Detailed),
         "niceonly" => Ok(SearchMode::Niceonly),
+        "nearmiss" => Ok(SearchMode::NearMiss),
         _ => Err(format!("Failed to deserialize search mode: {i}")),
     }
 }
@@ -137,5 +138,6 @@ pub fn serialize_searchmode(i: SearchMode) -> String {
     match i {
         SearchMode::Detailed => "detailed".to_string(),
         SearchMode::Niceonly => "niceonly".to_string(),
+        SearchMode::NearMiss => "nearmiss".to_string(),
     }
 }
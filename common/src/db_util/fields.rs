@@ -14,6 +14,7 @@ table! {
         canon_submission_id -> Nullable<Integer>,
         check_level -> Integer,
         prioritize -> Bool,
+        canon_updated_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -30,6 +31,7 @@ struct FieldPrivate {
     canon_submission_id: Option<i32>,
     check_level: i32,
     prioritize: bool,
+    canon_updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Insertable)]
@@ -54,6 +56,7 @@ fn private_to_public(p: FieldPrivate) -> Result<FieldRecord, String> {
         canon_submission_id: opti32_to_optu32(p.canon_submission_id)?,
         check_level: i32_to_u8(p.check_level)?,
         prioritize: p.prioritize,
+        canon_updated_at: p.canon_updated_at,
     })
 }
 
@@ -70,6 +73,7 @@ fn public_to_private(p: FieldRecord) -> Result<FieldPrivate, String> {
         canon_submission_id: optu32_to_opti32(p.canon_submission_id)?,
         check_level: u8_to_i32(p.check_level)?,
         prioritize: p.prioritize,
+        canon_updated_at: p.canon_updated_at,
     })
 }
 
@@ -118,6 +122,24 @@ pub fn get_field_by_id(conn: &mut PgConnection, row_id: u128) -> Result<FieldRec
         .and_then(private_to_public)
 }
 
+pub fn get_max_field_end_in_base(
+    conn: &mut PgConnection,
+    base: u32,
+) -> Result<Option<u128>, String> {
+    use self::fields::dsl::*;
+
+    let base = conversions::u32_to_i32(base)?;
+    let max_end: Option<BigDecimal> = fields
+        .filter(base_id.eq(base))
+        .select(range_end)
+        .order(range_end.desc())
+        .first(conn)
+        .optional()
+        .map_err(|err| err.to_string())?;
+
+    max_end.map(conversions::bigdec_to_u128).transpose()
+}
+
 pub fn get_fields_in_base(conn: &mut PgConnection, base: u32) -> Result<Vec<FieldRecord>, String> {
     use self::fields::dsl::*;
 
@@ -183,6 +205,8 @@ pub fn get_fields_in_base_with_detailed_subs(
 }
 
 /// Finds the next field that matches the criteria, updates last_claim_time, and returns it.
+/// The next strategy hands out prioritized fields first, then goes in order of ID.
+/// If a base is given, only fields in that base are considered.
 /// Returns Ok(None) if no matching fields are found.
 pub fn try_claim_field(
     conn: &mut PgConnection,
@@ -190,12 +214,14 @@ pub fn try_claim_field(
     maximum_timestamp: DateTime<Utc>,
     maximum_check_level: u8,
     maximum_size: u128,
+    only_base: Option<u32>,
 ) -> Result<Option<FieldRecord>, String> {
     use diesel::sql_query;
-    use diesel::sql_types::{Integer, Numeric, Timestamptz};
+    use diesel::sql_types::{Integer, Nullable, Numeric, Timestamptz};
 
     let maximum_check_level = conversions::u8_to_i32(maximum_check_level)?;
     let maximum_size = conversions::u128_to_bigdec(maximum_size)?;
+    let only_base = only_base.map(conversions::u32_to_i32).transpose()?;
 
     let query = match claim_strategy {
         FieldClaimStrategy::Next => {
@@ -206,7 +232,8 @@ pub fn try_claim_field(
                 WHERE (last_claim_time <= $1 OR last_claim_time IS NULL)
                 AND check_level <= $2
                 AND range_size <= $3
-                ORDER BY id ASC
+                AND ($4::integer IS NULL OR base_id = $4)
+                ORDER BY prioritize DESC, id ASC
                 LIMIT 1
             )
             RETURNING *;"
@@ -219,11 +246,28 @@ pub fn try_claim_field(
                 WHERE (last_claim_time <= $1 OR last_claim_time IS NULL)
                 AND check_level <= $2
                 AND range_size <= $3
+                AND ($4::integer IS NULL OR base_id = $4)
                 ORDER BY RANDOM() ASC
                 LIMIT 1
             )
             RETURNING *;"
         }
+        FieldClaimStrategy::WeightedRandom => {
+            // weighted sampling without loading the rows: each field gets an exponential
+            // random key scaled by its weight, and the smallest key wins
+            "UPDATE fields
+            SET last_claim_time = NOW()
+            WHERE id = (
+                SELECT id FROM fields
+                WHERE (last_claim_time <= $1 OR last_claim_time IS NULL)
+                AND check_level <= $2
+                AND range_size <= $3
+                AND ($4::integer IS NULL OR base_id = $4)
+                ORDER BY -LN(1.0 - RANDOM()) / ($2 - check_level + 1) ASC
+                LIMIT 1
+            )
+            RETURNING *;"
+        }
     }
     .to_string();
 
@@ -231,6 +275,7 @@ pub fn try_claim_field(
         .bind::<Timestamptz, _>(maximum_timestamp)
         .bind::<Integer, _>(maximum_check_level)
         .bind::<Numeric, _>(maximum_size)
+        .bind::<Nullable<Integer>, _>(only_base)
         .get_result::<FieldPrivate>(conn)
         .optional()
         .map_err(|err| err.to_string())
@@ -284,6 +329,51 @@ pub fn get_minimum_cl_by_range(
     conversions::i32_to_u8(result)
 }
 
+#[derive(QueryableByName)]
+struct RangeSummaryPrivate {
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    checked_niceonly: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    checked_detailed: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    minimum_cl: i32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    last_canon_update: Option<DateTime<Utc>>,
+}
+
+pub fn get_range_summary(
+    conn: &mut PgConnection,
+    start: u128,
+    end: u128,
+) -> Result<RangeSummary, String> {
+    use diesel::sql_query;
+    use diesel::sql_types::Numeric;
+
+    let in_range_start = conversions::u128_to_bigdec(start)?;
+    let in_range_end = conversions::u128_to_bigdec(end)?;
+
+    let query = "SELECT
+            COALESCE(SUM(range_size) FILTER (WHERE check_level >= 1), 0) AS checked_niceonly,
+            COALESCE(SUM(range_size) FILTER (WHERE check_level >= 2), 0) AS checked_detailed,
+            COALESCE(MIN(check_level), 0) AS minimum_cl,
+            MAX(canon_updated_at) AS last_canon_update
+        FROM fields
+        WHERE range_start >= $1 AND range_end <= $2;";
+
+    let summary: RangeSummaryPrivate = sql_query(query)
+        .bind::<Numeric, _>(in_range_start)
+        .bind::<Numeric, _>(in_range_end)
+        .get_result(conn)
+        .map_err(|err| err.to_string())?;
+
+    Ok(RangeSummary {
+        checked_niceonly: conversions::bigdec_to_u128(summary.checked_niceonly)?,
+        checked_detailed: conversions::bigdec_to_u128(summary.checked_detailed)?,
+        minimum_cl: conversions::i32_to_u8(summary.minimum_cl)?,
+        last_canon_update: summary.last_canon_update,
+    })
+}
+
 pub fn update_field(
     conn: &mut PgConnection,
     row_id: u128,
@@ -301,6 +391,40 @@ pub fn update_field(
         .and_then(private_to_public)
 }
 
+pub fn set_field_prioritize(
+    conn: &mut PgConnection,
+    field_id: u128,
+    in_prioritize: bool,
+) -> Result<Option<FieldRecord>, String> {
+    use self::fields::dsl::*;
+
+    let field_id = conversions::u128_to_i64(field_id)?;
+
+    diesel::update(fields.filter(id.eq(field_id)))
+        .set(prioritize.eq(in_prioritize))
+        .get_result::<FieldPrivate>(conn)
+        .optional()
+        .map_err(|err| err.to_string())
+        .and_then(|opt| opt.map_or(Ok(None), |rec| private_to_public(rec).map(Some)))
+}
+
+pub fn reset_field(conn: &mut PgConnection, field_id: u128) -> Result<Option<FieldRecord>, String> {
+    use self::fields::dsl::*;
+
+    let field_id = conversions::u128_to_i64(field_id)?;
+
+    diesel::update(fields.filter(id.eq(field_id)))
+        .set((
+            canon_submission_id.eq(None::<i32>),
+            check_level.eq(0),
+            canon_updated_at.eq(Some(Utc::now())),
+        ))
+        .get_result::<FieldPrivate>(conn)
+        .optional()
+        .map_err(|err| err.to_string())
+        .and_then(|opt| opt.map_or(Ok(None), |rec| private_to_public(rec).map(Some)))
+}
+
 pub fn update_field_canon_and_cl(
     conn: &mut PgConnection,
     field_id: u128,
@@ -318,6 +442,7 @@ pub fn update_field_canon_and_cl(
         .set((
             canon_submission_id.eq(submission_id),
             check_level.eq(in_check_level),
+            canon_updated_at.eq(Some(Utc::now())),
         ))
         .execute(conn)
         .map_err(|err| err.to_string())?;
@@ -4,6 +4,7 @@ use super::*;
 
 use bigdecimal::{BigDecimal, ToPrimitive};
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::table;
 use serde_json::Value;
 
@@ -14,6 +15,30 @@ mod conversions;
 mod fields;
 mod submissions;
 
+/// Carries our String errors out of a diesel transaction.
+struct TransactionError(String);
+
+impl From<diesel::result::Error> for TransactionError {
+    fn from(err: diesel::result::Error) -> Self {
+        TransactionError(err.to_string())
+    }
+}
+
+/// Run some queries in a single transaction, rolling everything back if any of them fail.
+fn run_transaction<T>(
+    conn: &mut PgConnection,
+    queries: impl FnOnce(&mut PgConnection) -> Result<T, String>,
+) -> Result<T, String> {
+    conn.transaction(|conn| queries(conn).map_err(TransactionError))
+        .map_err(|err| err.0)
+}
+
+/// A pool of database connections.
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// A single connection checked out from the pool.
+pub type PgPooledConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
 /// Get a single database connection.
 pub fn get_database_connection() -> PgConnection {
     dotenv().ok();
@@ -22,11 +47,51 @@ pub fn get_database_connection() -> PgConnection {
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
+/// Read an optional number from the environment, panicking if it is set but invalid.
+fn read_env_u32(key: &str) -> Option<u32> {
+    env::var(key).ok().map(|v| {
+        v.parse()
+            .unwrap_or_else(|_| panic!("{key} must be a positive integer"))
+    })
+}
+
+/// Get a pool of database connections.
+/// The size can be set with `DATABASE_POOL_SIZE` and `DATABASE_POOL_MIN_IDLE`,
+/// otherwise the r2d2 defaults are used.
+pub fn get_database_pool() -> PgPool {
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    let mut builder = Pool::builder().min_idle(read_env_u32("DATABASE_POOL_MIN_IDLE"));
+    if let Some(max_size) = read_env_u32("DATABASE_POOL_SIZE") {
+        builder = builder.max_size(max_size);
+    }
+    builder
+        .build(manager)
+        .expect("Error building the database connection pool")
+}
+
+/// Run a trivial query to check that the database is reachable.
+pub fn ping(conn: &mut PgConnection) -> Result<(), String> {
+    diesel::sql_query("SELECT 1")
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
 /// Get a base record (base range plus cached stats).
 pub fn get_base_by_id(conn: &mut PgConnection, base: u32) -> Result<BaseRecord, String> {
     bases::get_base_by_id(conn, base)
 }
 
+/// Get a base record, or None if the base does not exist.
+pub fn try_get_base_by_id(
+    conn: &mut PgConnection,
+    base: u32,
+) -> Result<Option<BaseRecord>, String> {
+    bases::try_get_base_by_id(conn, base)
+}
+
 /// Get all base records.
 pub fn get_all_bases(conn: &mut PgConnection) -> Result<Vec<BaseRecord>, String> {
     bases::get_all_bases(conn)
@@ -71,8 +136,9 @@ pub fn insert_new_chunks(
 }
 
 /// Reassign chunk associations for all fields in a certain base.
+/// Runs in one transaction so a failure never leaves the base half-reassigned.
 pub fn reassign_fields_to_chunks(conn: &mut PgConnection, base: u32) -> Result<(), String> {
-    chunks::reassign_fields_to_chunks(conn, base)
+    run_transaction(conn, |conn| chunks::reassign_fields_to_chunks(conn, base))
 }
 
 /// Update a chunk's calculated statistics.
@@ -94,6 +160,14 @@ pub fn get_fields_in_base(conn: &mut PgConnection, base: u32) -> Result<Vec<Fiel
     fields::get_fields_in_base(conn, base)
 }
 
+/// Get the end of the last field in a base, or None if the base has no fields yet.
+pub fn get_max_field_end_in_base(
+    conn: &mut PgConnection,
+    base: u32,
+) -> Result<Option<u128>, String> {
+    fields::get_max_field_end_in_base(conn, base)
+}
+
 /// Get all field records in a particular range
 pub fn get_fields_in_range(
     conn: &mut PgConnection,
@@ -111,7 +185,7 @@ pub fn get_fields_in_base_with_detailed_subs(
     fields::get_fields_in_base_with_detailed_subs(conn, base)
 }
 
-/// Try to claim a valid field.
+/// Try to claim a valid field, optionally restricted to one base.
 /// Returns Ok(None) if no matching fields are found.
 pub fn try_claim_field(
     conn: &mut PgConnection,
@@ -119,6 +193,7 @@ pub fn try_claim_field(
     maximum_timestamp: DateTime<Utc>,
     maximum_check_level: u8,
     maximum_size: u128,
+    only_base: Option<u32>,
 ) -> Result<Option<FieldRecord>, String> {
     fields::try_claim_field(
         conn,
@@ -126,9 +201,75 @@ pub fn try_claim_field(
         maximum_timestamp,
         maximum_check_level,
         maximum_size,
+        only_base,
     )
 }
 
+/// Try to claim a valid field and log the claim, both in one transaction.
+/// Returns Ok(None) if no matching fields are found.
+#[allow(clippy::too_many_arguments)]
+pub fn try_claim_and_log_field(
+    conn: &mut PgConnection,
+    claim_strategy: FieldClaimStrategy,
+    maximum_timestamp: DateTime<Utc>,
+    maximum_check_level: u8,
+    maximum_size: u128,
+    only_base: Option<u32>,
+    search_mode: SearchMode,
+    user_ip: String,
+) -> Result<Option<(FieldRecord, ClaimRecord)>, String> {
+    run_transaction(conn, |conn| {
+        let Some(field) = fields::try_claim_field(
+            conn,
+            claim_strategy,
+            maximum_timestamp,
+            maximum_check_level,
+            maximum_size,
+            only_base,
+        )?
+        else {
+            return Ok(None);
+        };
+        let claim = claims::insert_claim(conn, field.field_id, search_mode, user_ip)?;
+        Ok(Some((field, claim)))
+    })
+}
+
+/// Claim up to `count` fields and log a claim for each, all in one transaction.
+/// Returns fewer than requested (possibly none) if there aren't enough matching fields.
+#[allow(clippy::too_many_arguments)]
+pub fn try_claim_fields(
+    conn: &mut PgConnection,
+    count: usize,
+    claim_strategy: FieldClaimStrategy,
+    maximum_timestamp: DateTime<Utc>,
+    maximum_check_level: u8,
+    maximum_size: u128,
+    only_base: Option<u32>,
+    search_mode: SearchMode,
+    user_ip: String,
+) -> Result<Vec<(FieldRecord, ClaimRecord)>, String> {
+    run_transaction(conn, |conn| {
+        let mut claimed = Vec::new();
+        while claimed.len() < count {
+            let Some(field) = fields::try_claim_field(
+                conn,
+                claim_strategy,
+                maximum_timestamp,
+                maximum_check_level,
+                maximum_size,
+                only_base,
+            )?
+            else {
+                break;
+            };
+            let claim = claims::insert_claim(conn, field.field_id, search_mode, user_ip.clone())?;
+            claimed.push((field, claim));
+        }
+        Ok(claimed)
+    })
+}
+
 /// Insert a bunch of new fields.
 /// Only called by admin scripts.
 pub fn insert_new_fields(
@@ -149,6 +290,16 @@ pub fn update_field_canon_and_cl(
     fields::update_field_canon_and_cl(conn, field_id, submission_id, check_level)
 }
 
+/// Mark a field to be handed out before others, or clear the mark.
+/// Returns Ok(None) if the field does not exist.
+pub fn set_field_prioritize(
+    conn: &mut PgConnection,
+    field_id: u128,
+    prioritize: bool,
+) -> Result<Option<FieldRecord>, String> {
+    fields::set_field_prioritize(conn, field_id, prioritize)
+}
+
 /// Insert a claim with basic information.
 pub fn insert_claim(
     conn: &mut PgConnection,
@@ -166,26 +317,76 @@ pub fn get_claim_by_id(conn: &mut PgConnection, claim_id: u128) -> Result<ClaimR
 
 /// Push a new submission to the database.
 /// This is assumed to pass some basic validation but it is not considered canon until the consensus is reached.
+/// Suspicious submissions are kept for review but never count toward consensus.
+/// If a check level is given, the field is raised to it in the same transaction (never lowered).
+/// Returns Ok(None) and changes nothing if the claim already has a submission.
+#[allow(clippy::too_many_arguments)]
 pub fn insert_submission(
     conn: &mut PgConnection,
     claim_record: ClaimRecord,
     submit_data: DataToServer,
     user_ip: String,
+    suspicious: bool,
     distribution: Option<Vec<UniquesDistribution>>,
     numbers: Vec<NiceNumber>,
+    check_level: Option<u8>,
+) -> Result<Option<SubmissionRecord>, String> {
+    run_transaction(conn, |conn| {
+        let field_id = claim_record.field_id;
+        let Some(submission) = submissions::insert_submission(
+            conn,
+            claim_record,
+            submit_data,
+            user_ip,
+            suspicious,
+            distribution,
+            numbers,
+        )?
+        else {
+            return Ok(None);
+        };
+        if let Some(check_level) = check_level {
+            let field = fields::get_field_by_id(conn, field_id)?;
+            if field.check_level < check_level {
+                fields::update_field_canon_and_cl(
+                    conn,
+                    field_id,
+                    field.canon_submission_id,
+                    check_level,
+                )?;
+            }
+        }
+        Ok(Some(submission))
+    })
+}
+
+/// Push many new submissions to the database at once, e.g. when replaying old submissions.
+/// Either all of them are inserted or none are.
+pub fn insert_submissions_batch(
+    conn: &mut PgConnection,
+    new_submissions: Vec<NewSubmission>,
+) -> Result<Vec<SubmissionRecord>, String> {
+    submissions::insert_submissions_batch(conn, new_submissions)
+}
+
+/// Get a submission record by its ID.
+pub fn get_submission_by_id(
+    conn: &mut PgConnection,
+    submission_id: u128,
 ) -> Result<SubmissionRecord, String> {
-    submissions::insert_submission(
-        conn,
-        claim_record,
-        submit_data,
-        user_ip,
-        distribution,
-        numbers,
-    )
+    submissions::get_submission_by_id(conn, submission_id)
+}
+
+/// Get the first submission made for a claim, or Ok(None) if it hasn't been submitted yet.
+pub fn get_submission_by_claim_id(
+    conn: &mut PgConnection,
+    claim_id: u128,
+) -> Result<Option<SubmissionRecord>, String> {
+    submissions::get_submission_by_claim_id(conn, claim_id)
 }
 
 /// Get all submission records for a particular field.
-/// Only returns qualified and detailed submissions.
+/// Only returns qualified, detailed, and unsuspicious submissions.
 pub fn get_submissions_qualified_detailed_for_field(
     conn: &mut PgConnection,
     field_id: u128,
@@ -193,6 +394,55 @@ pub fn get_submissions_qualified_detailed_for_field(
     submissions::get_submissions_qualified_detailed_for_field(conn, field_id)
 }
 
+/// Mark submissions as disqualified so they are no longer counted anywhere.
+pub fn disqualify_submissions(
+    conn: &mut PgConnection,
+    submission_ids: &[u128],
+) -> Result<(), String> {
+    submissions::disqualify_submissions(conn, submission_ids)
+}
+
+/// Send a field back to be searched from scratch, all in one transaction.
+/// Clears the canon submission and check level and disqualifies every submission for it.
+/// Returns the number of submissions disqualified, or Ok(None) if the field does not exist.
+pub fn reset_field_for_reprocessing(
+    conn: &mut PgConnection,
+    field_id: u128,
+) -> Result<Option<usize>, String> {
+    run_transaction(conn, |conn| {
+        if fields::reset_field(conn, field_id)?.is_none() {
+            return Ok(None);
+        }
+        submissions::disqualify_submissions_for_field(conn, field_id).map(Some)
+    })
+}
+
+/// Count every completely nice number found so far, across all bases.
+/// Numbers found by more than one submission are only counted once.
+/// Disqualified and suspicious submissions are not counted.
+pub fn count_all_nice_numbers(conn: &mut PgConnection) -> Result<u128, String> {
+    submissions::count_all_nice_numbers(conn)
+}
+
+/// Get a user's most recent submissions, newest first.
+pub fn get_submissions_by_user(
+    conn: &mut PgConnection,
+    username: &str,
+    limit: u32,
+) -> Result<Vec<SubmissionRecord>, String> {
+    submissions::get_submissions_by_user(conn, username, limit)
+}
+
+/// Get how many submissions each user has made and how much they have checked, largest first.
+/// Disqualified and suspicious submissions are not counted.
+pub fn get_submission_counts_by_user(
+    conn: &mut PgConnection,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<UserContribution>, String> {
+    submissions::get_submission_counts_by_user(conn, limit, offset)
+}
+
 /// Get the range that has reached the given check level.
 pub fn get_count_checked_by_range(
     conn: &mut PgConnection,
@@ -212,6 +462,15 @@ pub fn get_minimum_cl_by_range(
     fields::get_minimum_cl_by_range(conn, start, end)
 }
 
+/// Get the checked counts and minimum check level for the range in one query.
+pub fn get_range_summary(
+    conn: &mut PgConnection,
+    start: u128,
+    end: u128,
+) -> Result<RangeSummary, String> {
+    fields::get_range_summary(conn, start, end)
+}
+
 /// Get all canon submissions in a particular range.
 pub fn get_canon_submissions_by_range(
     conn: &mut PgConnection,
@@ -220,3 +479,303 @@ pub fn get_canon_submissions_by_range(
 ) -> Result<Vec<SubmissionRecord>, String> {
     submissions::get_canon_submissions_by_range(conn, start, end)
 }
+
+/// Get the time of the most recent submission for any field in a range, if there are any.
+pub fn get_latest_submit_time_by_range(
+    conn: &mut PgConnection,
+    start: u128,
+    end: u128,
+) -> Result<Option<DateTime<Utc>>, String> {
+    submissions::get_latest_submit_time_by_range(conn, start, end)
+}
+
+/// Get one page of canon submissions in a base, ordered by ID.
+/// Pass the last ID from the previous page to get the next one, so memory stays bounded.
+pub fn get_canon_submissions_in_base_page(
+    conn: &mut PgConnection,
+    base: u32,
+    after_id: Option<u128>,
+    limit: u32,
+) -> Result<Vec<SubmissionRecord>, String> {
+    submissions::get_canon_submissions_in_base_page(conn, base, after_id, limit)
+}
+
+#[cfg(all(test, feature = "database"))]
+mod tests {
+    use super::*;
+
+    /// A base far outside the real search so the test never touches live data.
+    const TEST_BASE: u32 = 9_999;
+
+    fn setup_unassigned_base(conn: &mut PgConnection) {
+        let base_size = FieldSize::new(0, 1_000);
+        insert_new_base(conn, TEST_BASE, base_size.clone()).unwrap();
+        insert_new_chunks(conn, TEST_BASE, vec![base_size]).unwrap();
+        insert_new_fields(
+            conn,
+            TEST_BASE,
+            generate_fields::break_range_into_fields(0, 1_000, 100),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reassign_fields_rolls_back_on_error() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let result: Result<(), String> = run_transaction(&mut conn, |conn| {
+            chunks::reassign_fields_to_chunks(conn, TEST_BASE)?;
+            Err("simulated failure".to_string())
+        });
+        assert_eq!(result, Err("simulated failure".to_string()));
+        let fields = get_fields_in_base(&mut conn, TEST_BASE).unwrap();
+        assert_eq!(fields.len(), 10);
+        assert!(fields.iter().all(|f| f.chunk_id.is_none()));
+
+        reassign_fields_to_chunks(&mut conn, TEST_BASE).unwrap();
+        let fields = get_fields_in_base(&mut conn, TEST_BASE).unwrap();
+        assert!(fields.iter().all(|f| f.chunk_id.is_some()));
+    }
+
+    #[test]
+    fn next_claim_prefers_prioritized_fields() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let last = get_fields_in_base(&mut conn, TEST_BASE)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let prioritized = FieldRecord {
+            prioritize: true,
+            ..last.clone()
+        };
+        fields::update_field(&mut conn, last.field_id, prioritized).unwrap();
+
+        let claimed = try_claim_field(
+            &mut conn,
+            FieldClaimStrategy::Next,
+            Utc::now(),
+            0,
+            DEFAULT_FIELD_SIZE,
+            Some(TEST_BASE),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(claimed.field_id, last.field_id);
+    }
+
+    #[test]
+    fn batch_claim_stays_in_base() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        // ask for more than the base has, only its own fields come back
+        // NOW() is frozen for the whole test transaction, so only unclaimed fields can match
+        let claimed = try_claim_fields(
+            &mut conn,
+            20,
+            FieldClaimStrategy::Next,
+            Utc::now() - chrono::TimeDelta::hours(1),
+            0,
+            DEFAULT_FIELD_SIZE,
+            Some(TEST_BASE),
+            SearchMode::Niceonly,
+            "unknown".to_string(),
+        )
+        .unwrap();
+        assert_eq!(claimed.len(), 10);
+        assert!(claimed.iter().all(|(field, _)| field.base == TEST_BASE));
+    }
+
+    #[test]
+    fn submission_lookup_by_claim_id() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let (_, claim_record) = try_claim_and_log_field(
+            &mut conn,
+            FieldClaimStrategy::Next,
+            Utc::now(),
+            0,
+            DEFAULT_FIELD_SIZE,
+            Some(TEST_BASE),
+            SearchMode::Niceonly,
+            "unknown".to_string(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            get_submission_by_claim_id(&mut conn, claim_record.claim_id).unwrap(),
+            None
+        );
+
+        let submit_data = DataToServer {
+            claim_id: claim_record.claim_id,
+            username: "test".to_string(),
+            client_version: CLIENT_VERSION.to_string(),
+            unique_distribution: None,
+            nice_numbers: Vec::new(),
+        };
+        let inserted = insert_submission(
+            &mut conn,
+            claim_record.clone(),
+            submit_data,
+            "unknown".to_string(),
+            false,
+            None,
+            Vec::new(),
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        let found = get_submission_by_claim_id(&mut conn, claim_record.claim_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.submission_id, inserted.submission_id);
+        assert_eq!(found.claim_id, claim_record.claim_id);
+    }
+
+    #[test]
+    fn repeated_submission_is_not_saved() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let (field_record, claim_record) = try_claim_and_log_field(
+            &mut conn,
+            FieldClaimStrategy::Next,
+            Utc::now(),
+            0,
+            DEFAULT_FIELD_SIZE,
+            Some(TEST_BASE),
+            SearchMode::Niceonly,
+            "unknown".to_string(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(field_record.check_level, 0);
+
+        let submit_data = DataToServer {
+            claim_id: claim_record.claim_id,
+            username: "test".to_string(),
+            client_version: CLIENT_VERSION.to_string(),
+            unique_distribution: None,
+            nice_numbers: Vec::new(),
+        };
+        let insert = |conn: &mut PgConnection, check_level| {
+            insert_submission(
+                conn,
+                claim_record.clone(),
+                submit_data.clone(),
+                "unknown".to_string(),
+                false,
+                None,
+                Vec::new(),
+                check_level,
+            )
+            .unwrap()
+        };
+
+        // the first submission is saved and raises the check level
+        let inserted = insert(&mut conn, Some(1)).unwrap();
+        let field = get_field_by_id(&mut conn, field_record.field_id).unwrap();
+        assert_eq!(field.check_level, 1);
+
+        // the repeat saves nothing and leaves the field alone
+        assert_eq!(insert(&mut conn, Some(2)), None);
+        let field = get_field_by_id(&mut conn, field_record.field_id).unwrap();
+        assert_eq!(field.check_level, 1);
+        let found = get_submission_by_claim_id(&mut conn, claim_record.claim_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.submission_id, inserted.submission_id);
+    }
+
+    #[test]
+    fn canon_change_shows_in_range_summary() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let summary = get_range_summary(&mut conn, 0, 1_000).unwrap();
+        assert_eq!(summary.last_canon_update, None);
+
+        // swapping the canon submission leaves the checked totals alone, but not the timestamp
+        let field = get_fields_in_base(&mut conn, TEST_BASE).unwrap().remove(0);
+        let before = Utc::now();
+        update_field_canon_and_cl(&mut conn, field.field_id, None, field.check_level).unwrap();
+        let summary = get_range_summary(&mut conn, 0, 1_000).unwrap();
+        assert_eq!(summary.checked_niceonly, 0);
+        assert!(summary.last_canon_update.is_some_and(|t| t >= before));
+    }
+
+    #[test]
+    fn suspicious_submissions_are_not_counted() {
+        let mut conn = get_database_connection();
+        conn.begin_test_transaction().unwrap();
+        setup_unassigned_base(&mut conn);
+
+        let username = "suspicious-count-test".to_string();
+        let submit = |conn: &mut PgConnection, suspicious| {
+            let (_, claim_record) = try_claim_and_log_field(
+                conn,
+                FieldClaimStrategy::Next,
+                Utc::now(),
+                0,
+                DEFAULT_FIELD_SIZE,
+                Some(TEST_BASE),
+                SearchMode::Niceonly,
+                "unknown".to_string(),
+            )
+            .unwrap()
+            .unwrap();
+            let submit_data = DataToServer {
+                claim_id: claim_record.claim_id,
+                username: username.clone(),
+                client_version: CLIENT_VERSION.to_string(),
+                unique_distribution: None,
+                nice_numbers: Vec::new(),
+            };
+            let number = NiceNumber {
+                number: claim_record.field_id,
+                num_uniques: TEST_BASE,
+                base: TEST_BASE,
+                niceness: 1.0,
+            };
+            insert_submission(
+                conn,
+                claim_record,
+                submit_data,
+                "unknown".to_string(),
+                suspicious,
+                None,
+                vec![number],
+                Some(1),
+            )
+            .unwrap()
+            .unwrap();
+        };
+        let user_count = |conn: &mut PgConnection| {
+            get_submission_counts_by_user(conn, u32::MAX, 0)
+                .unwrap()
+                .into_iter()
+                .find(|c| c.username == username)
+                .map(|c| c.submission_count)
+        };
+
+        let nice_before = count_all_nice_numbers(&mut conn).unwrap();
+        submit(&mut conn, true);
+        assert_eq!(count_all_nice_numbers(&mut conn).unwrap(), nice_before);
+        assert_eq!(user_count(&mut conn), None);
+
+        submit(&mut conn, false);
+        assert_eq!(count_all_nice_numbers(&mut conn).unwrap(), nice_before + 1);
+        assert_eq!(user_count(&mut conn), Some(1));
+    }
+}
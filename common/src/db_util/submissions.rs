@@ -14,6 +14,7 @@ table! {
         user_ip -> Varchar,
         client_version -> Varchar,
         disqualified -> Bool,
+        suspicious -> Bool,
         distribution -> Nullable<Jsonb>,
         numbers -> Jsonb,
     }
@@ -32,10 +33,33 @@ struct SubmissionPrivate {
     user_ip: String,
     client_version: String,
     disqualified: bool,
+    suspicious: bool,
     distribution: Option<Value>,
     numbers: Value,
 }
 
+#[derive(QueryableByName)]
+struct UserContributionPrivate {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    username: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    submission_count: i64,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    checked_size: BigDecimal,
+}
+
+#[derive(QueryableByName)]
+struct NiceCountPrivate {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+}
+
+#[derive(QueryableByName)]
+struct LatestSubmitPrivate {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    latest: Option<DateTime<Utc>>,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = submissions)]
 struct SubmissionPrivateNew {
@@ -46,6 +70,7 @@ struct SubmissionPrivateNew {
     username: String,
     user_ip: String,
     client_version: String,
+    suspicious: bool,
     distribution: Option<Value>,
     numbers: Value,
 }
@@ -54,7 +79,7 @@ fn private_to_public(p: SubmissionPrivate) -> Result<SubmissionRecord, String> {
     use conversions::*;
     Ok(SubmissionRecord {
         submission_id: i64_to_u128(p.id)?,
-        claim_id: i64_to_u128(p.id)?,
+        claim_id: i32_to_u128(p.claim_id)?,
         field_id: i32_to_u128(p.field_id)?,
         search_mode: deserialize_searchmode(p.search_mode)?,
         submit_time: p.submit_time,
@@ -63,6 +88,7 @@ fn private_to_public(p: SubmissionPrivate) -> Result<SubmissionRecord, String> {
         user_ip: p.user_ip,
         client_version: p.client_version,
         disqualified: p.disqualified,
+        suspicious: p.suspicious,
         distribution: deserialize_opt_distribution(p.distribution)?,
         numbers: deserialize_numbers(p.numbers)?,
     })
@@ -81,6 +107,7 @@ fn public_to_private(p: SubmissionRecord) -> Result<SubmissionPrivate, String> {
         user_ip: p.user_ip,
         client_version: p.client_version,
         disqualified: p.disqualified,
+        suspicious: p.suspicious,
         distribution: serialize_opt_distribution(p.distribution)?,
         numbers: serialize_numbers(p.numbers)?,
     })
@@ -90,6 +117,7 @@ fn build_new_row(
     claim_record: ClaimRecord,
     submit_data: DataToServer,
     user_ip: String,
+    suspicious: bool,
     distribution: Option<Vec<UniquesDistribution>>,
     numbers: Vec<NiceNumber>,
 ) -> Result<SubmissionPrivateNew, String> {
@@ -102,6 +130,7 @@ fn build_new_row(
         username: submit_data.username,
         user_ip,
         client_version: submit_data.client_version,
+        suspicious,
         distribution: serialize_opt_distribution(distribution)?,
         numbers: serialize_numbers(numbers)?,
     })
@@ -112,24 +141,67 @@ pub fn insert_submission(
     claim_record: ClaimRecord,
     submit_data: DataToServer,
     input_user_ip: String,
+    input_suspicious: bool,
     input_distribution: Option<Vec<UniquesDistribution>>,
     input_numbers: Vec<NiceNumber>,
-) -> Result<SubmissionRecord, String> {
+) -> Result<Option<SubmissionRecord>, String> {
     use self::submissions::dsl::*;
 
     let insert_row = build_new_row(
         claim_record,
         submit_data,
         input_user_ip,
+        input_suspicious,
         input_distribution,
         input_numbers,
     )?;
 
+    // each claim can only be submitted once, a conflict inserts nothing and returns no row
     diesel::insert_into(submissions)
         .values(&insert_row)
+        .on_conflict(claim_id)
+        .do_nothing()
         .get_result(conn)
-        .map_err(|err| err.to_string())
-        .and_then(private_to_public)
+        .optional()
+        .map_err(|err| err.to_string())?
+        .map(private_to_public)
+        .transpose()
+}
+
+pub fn insert_submissions_batch(
+    conn: &mut PgConnection,
+    new_submissions: Vec<NewSubmission>,
+) -> Result<Vec<SubmissionRecord>, String> {
+    use self::submissions::dsl::*;
+
+    let insert_rows: Vec<SubmissionPrivateNew> = new_submissions
+        .into_iter()
+        .map(|s| {
+            build_new_row(
+                s.claim_record,
+                s.submit_data,
+                s.user_ip,
+                s.suspicious,
+                s.distribution,
+                s.numbers,
+            )
+        })
+        .collect::<Result<Vec<SubmissionPrivateNew>, String>>()?;
+
+    run_transaction(conn, |conn| {
+        let mut inserted = Vec::with_capacity(insert_rows.len());
+        // chunk it out to stay under the postgres limit of 65535 bind parameters
+        for chunk in insert_rows.chunks(5000) {
+            let items_private: Vec<SubmissionPrivate> = diesel::insert_into(submissions)
+                .values(chunk)
+                .get_results(conn)
+                .map_err(|err| err.to_string())?;
+            for item in items_private {
+                inserted.push(private_to_public(item)?);
+            }
+        }
+        Ok(inserted)
+    })
 }
 
 pub fn get_submission_by_id(
@@ -147,6 +219,24 @@ pub fn get_submission_by_id(
         .and_then(private_to_public)
 }
 
+pub fn get_submission_by_claim_id(
+    conn: &mut PgConnection,
+    input_claim_id: u128,
+) -> Result<Option<SubmissionRecord>, String> {
+    use self::submissions::dsl::*;
+
+    let input_claim_id = conversions::u128_to_i32(input_claim_id)?;
+
+    submissions
+        .filter(claim_id.eq(input_claim_id))
+        .order(id.asc())
+        .first::<SubmissionPrivate>(conn)
+        .optional()
+        .map_err(|err| err.to_string())?
+        .map(private_to_public)
+        .transpose()
+}
+
 pub fn get_canon_submissions_by_range(
     conn: &mut PgConnection,
     start: u128,
@@ -176,6 +266,65 @@ pub fn get_canon_submissions_by_range(
         .collect::<Result<Vec<SubmissionRecord>, String>>()
 }
 
+pub fn get_latest_submit_time_by_range(
+    conn: &mut PgConnection,
+    start: u128,
+    end: u128,
+) -> Result<Option<DateTime<Utc>>, String> {
+    use diesel::sql_query;
+    use diesel::sql_types::Numeric;
+
+    let start = conversions::u128_to_bigdec(start)?;
+    let end = conversions::u128_to_bigdec(end)?;
+
+    let query = "SELECT MAX(s.submit_time) AS latest
+        FROM fields f
+        JOIN submissions s ON s.field_id = f.id
+        WHERE f.range_start >= $1
+        AND f.range_end <= $2;";
+
+    let result: LatestSubmitPrivate = sql_query(query)
+        .bind::<Numeric, _>(start)
+        .bind::<Numeric, _>(end)
+        .get_result(conn)
+        .map_err(|err| err.to_string())?;
+
+    Ok(result.latest)
+}
+
+pub fn get_canon_submissions_in_base_page(
+    conn: &mut PgConnection,
+    base: u32,
+    after_id: Option<u128>,
+    limit: u32,
+) -> Result<Vec<SubmissionRecord>, String> {
+    use diesel::sql_query;
+    use diesel::sql_types::{BigInt, Integer};
+
+    let base = conversions::u32_to_i32(base)?;
+    let after_id = after_id.map_or(Ok(0), conversions::u128_to_i64)?;
+
+    let query = "SELECT s.*
+        FROM fields f
+        JOIN submissions s ON f.canon_submission_id = s.id
+        WHERE f.base_id = $1
+        AND s.id > $2
+        ORDER BY s.id ASC
+        LIMIT $3;";
+
+    let items_private: Vec<SubmissionPrivate> = sql_query(query)
+        .bind::<Integer, _>(base)
+        .bind::<BigInt, _>(after_id)
+        .bind::<BigInt, _>(i64::from(limit))
+        .load(conn)
+        .map_err(|err| err.to_string())?;
+
+    items_private
+        .into_iter()
+        .map(private_to_public)
+        .collect::<Result<Vec<SubmissionRecord>, String>>()
+}
+
 pub fn get_submissions_qualified_detailed_for_field(
     conn: &mut PgConnection,
     input_field_id: u128,
@@ -185,11 +334,13 @@ pub fn get_submissions_qualified_detailed_for_field(
     let input_field_id = conversions::u128_to_i32(input_field_id)?;
     let input_search_mode = conversions::serialize_searchmode(SearchMode::Detailed);
     let input_disqualified = false;
+    let input_suspicious = false;
 
     let items_private: Vec<SubmissionPrivate> = submissions
         .filter(field_id.eq(input_field_id))
         .filter(search_mode.eq(input_search_mode))
         .filter(disqualified.eq(input_disqualified))
+        .filter(suspicious.eq(input_suspicious))
         .load(conn)
         .map_err(|err| err.to_string())?;
 
@@ -198,3 +349,112 @@ pub fn get_submissions_qualified_detailed_for_field(
         .map(private_to_public)
         .collect::<Result<Vec<SubmissionRecord>, String>>()
 }
+
+pub fn disqualify_submissions(conn: &mut PgConnection, row_ids: &[u128]) -> Result<(), String> {
+    use self::submissions::dsl::*;
+
+    let row_ids = row_ids
+        .iter()
+        .map(|row_id| conversions::u128_to_i64(*row_id))
+        .collect::<Result<Vec<i64>, String>>()?;
+
+    diesel::update(submissions.filter(id.eq_any(row_ids)))
+        .set(disqualified.eq(true))
+        .execute(conn)
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+pub fn disqualify_submissions_for_field(
+    conn: &mut PgConnection,
+    input_field_id: u128,
+) -> Result<usize, String> {
+    use self::submissions::dsl::*;
+
+    let input_field_id = conversions::u128_to_i32(input_field_id)?;
+
+    diesel::update(submissions.filter(field_id.eq(input_field_id)))
+        .set(disqualified.eq(true))
+        .execute(conn)
+        .map_err(|err| err.to_string())
+}
+
+pub fn get_submissions_by_user(
+    conn: &mut PgConnection,
+    input_username: &str,
+    limit: u32,
+) -> Result<Vec<SubmissionRecord>, String> {
+    use self::submissions::dsl::*;
+
+    let items_private: Vec<SubmissionPrivate> = submissions
+        .filter(username.eq(input_username))
+        .order(submit_time.desc())
+        .limit(i64::from(limit))
+        .load(conn)
+        .map_err(|err| err.to_string())?;
+
+    items_private
+        .into_iter()
+        .map(private_to_public)
+        .collect::<Result<Vec<SubmissionRecord>, String>>()
+}
+
+pub fn count_all_nice_numbers(conn: &mut PgConnection) -> Result<u128, String> {
+    use diesel::sql_query;
+
+    // the same number can be in several submissions for a field, so only count it once
+    let query = "SELECT COUNT(DISTINCT (f.base_id, n->>'number')) AS total
+        FROM submissions s
+        JOIN fields f ON s.field_id = f.id
+        CROSS JOIN LATERAL jsonb_array_elements(s.numbers) AS n
+        WHERE s.disqualified = false
+        AND s.suspicious = false
+        AND (n->>'num_uniques')::integer = f.base_id;";
+
+    let result: NiceCountPrivate = sql_query(query)
+        .get_result(conn)
+        .map_err(|err| err.to_string())?;
+
+    conversions::i64_to_u128(result.total)
+}
+
+pub fn get_submission_counts_by_user(
+    conn: &mut PgConnection,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<UserContribution>, String> {
+    use diesel::sql_query;
+    use diesel::sql_types::BigInt;
+
+    let limit = i64::from(limit);
+    let offset = i64::from(offset);
+
+    let query = "SELECT s.username,
+            COUNT(*) AS submission_count,
+            SUM(f.range_size) AS checked_size
+        FROM submissions s
+        JOIN fields f ON s.field_id = f.id
+        WHERE s.disqualified = false
+        AND s.suspicious = false
+        GROUP BY s.username
+        ORDER BY checked_size DESC, s.username ASC
+        LIMIT $1 OFFSET $2;";
+
+    let items_private: Vec<UserContributionPrivate> = sql_query(query)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load(conn)
+        .map_err(|err| err.to_string())?;
+
+    items_private
+        .into_iter()
+        .map(|p| {
+            Ok(UserContribution {
+                username: p.username,
+                submission_count: conversions::i64_to_u128(p.submission_count)?,
+                checked_size: conversions::bigdec_to_u128(p.checked_size)?,
+            })
+        })
+        .collect::<Result<Vec<UserContribution>, String>>()
+}
@@ -13,12 +13,28 @@ pub fn expand_distribution(
         .map(|d| UniquesDistribution {
             num_uniques: d.num_uniques,
             count: d.count,
-            niceness: d.num_uniques as f32 / base as f32,
+            niceness: NiceNumber::niceness_from(d.num_uniques, base),
             density: d.count as f32 / total_count as f32,
         })
         .collect()
 }
 
+/// Sum several partial distributions into one, sorted by number of uniques.
+pub fn merge(
+    dists: impl Iterator<Item = Vec<UniquesDistributionSimple>>,
+) -> Vec<UniquesDistributionSimple> {
+    let mut counts: HashMap<u32, u128> = HashMap::new();
+    for d in dists.flatten() {
+        *counts.entry(d.num_uniques).or_insert(0) += d.count;
+    }
+    let mut merged: Vec<UniquesDistributionSimple> = counts
+        .into_iter()
+        .map(|(num_uniques, count)| UniquesDistributionSimple { num_uniques, count })
+        .collect();
+    merged.sort_by_key(|d| d.num_uniques);
+    merged
+}
+
 pub fn downsample_distributions(
     submissions: &[SubmissionRecord],
     base: u32,
@@ -69,6 +85,41 @@ pub fn mean_stdev_from_distribution(distribution: &[UniquesDistribution]) -> (f3
     (mean, stdev)
 }
 
+/// Get the niceness at each requested quantile (between 0 and 1), weighted by count.
+/// Returns pairs of (quantile, niceness), or None if the distribution is empty.
+pub fn percentiles(
+    distribution: &[UniquesDistribution],
+    quantiles: &[f32],
+) -> Option<Vec<(f32, f32)>> {
+    let total_count: u128 = distribution.iter().map(|d| d.count).sum();
+    if total_count == 0 {
+        return None;
+    }
+
+    let mut sorted: Vec<&UniquesDistribution> = distribution.iter().collect();
+    sorted.sort_by_key(|d| d.num_uniques);
+
+    Some(
+        quantiles
+            .iter()
+            .map(|&q| {
+                // nearest-rank: the first bucket that reaches the target count
+                let target =
+                    ((q.clamp(0.0, 1.0) as f64 * total_count as f64).ceil() as u128).max(1);
+                let mut cumulative = 0;
+                let niceness = sorted
+                    .iter()
+                    .find(|d| {
+                        cumulative += d.count;
+                        cumulative >= target
+                    })
+                    .map_or(0.0, |d| d.niceness);
+                (q, niceness)
+            })
+            .collect(),
+    )
+}
+
 pub fn shrink_distribution(distribution: &[UniquesDistribution]) -> Vec<UniquesDistributionSimple> {
     distribution
         .iter()
@@ -80,3 +131,49 @@ pub fn shrink_distribution(distribution: &[UniquesDistribution]) -> Vec<UniquesD
 }
 
 // TODO: tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let distribution = expand_distribution(
+            &[
+                UniquesDistributionSimple {
+                    num_uniques: 5,
+                    count: 50,
+                },
+                UniquesDistributionSimple {
+                    num_uniques: 8,
+                    count: 40,
+                },
+                UniquesDistributionSimple {
+                    num_uniques: 10,
+                    count: 10,
+                },
+            ],
+            10,
+        );
+        assert_eq!(
+            percentiles(&distribution, &[0.5, 0.9, 0.99]),
+            Some(vec![(0.5, 0.5), (0.9, 0.8), (0.99, 1.0)])
+        );
+    }
+
+    #[test]
+    fn test_merge_matches_single_pass() {
+        let single = client_process::process_range_detailed(47, 100, 10, None);
+        let first = client_process::process_range_detailed(47, 70, 10, None);
+        let second = client_process::process_range_detailed(70, 100, 10, None);
+        assert_eq!(
+            merge([first.distribution, second.distribution].into_iter()),
+            single.distribution
+        );
+    }
+
+    #[test]
+    fn test_percentiles_empty() {
+        assert_eq!(percentiles(&[], &[0.5]), None);
+    }
+}
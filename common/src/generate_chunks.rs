@@ -45,6 +45,22 @@ pub fn group_fields_into_chunks(fields: Vec<FieldSize>) -> Vec<FieldSize> {
     chunks
 }
 
+/// Get the part of a base range that comes after the last chunk, if any.
+/// Chunks are expected to be in ascending order. With no chunks, this is the whole base.
+pub fn get_range_after_chunks(base_range: &FieldSize, chunks: &[FieldSize]) -> Option<FieldSize> {
+    let range_start = chunks
+        .last()
+        .map_or(base_range.range_start, |c| c.range_end);
+    if range_start >= base_range.range_end {
+        return None;
+    }
+    Some(FieldSize {
+        range_start,
+        range_end: base_range.range_end,
+        range_size: base_range.range_end - range_start,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,4 +128,31 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_get_range_after_chunks() {
+        let base_range = base_range::get_base_range_u128(40).unwrap().unwrap();
+        let fields = generate_fields::break_range_into_fields(
+            base_range.range_start,
+            base_range.range_end,
+            1000000000,
+        );
+        let chunks = group_fields_into_chunks(fields);
+
+        // chunks that cover the whole base leave nothing behind
+        assert_eq!(get_range_after_chunks(&base_range, &chunks), None);
+
+        // dropping the final chunk leaves its range uncovered
+        let last_chunk = chunks.last().unwrap().clone();
+        assert_eq!(
+            get_range_after_chunks(&base_range, &chunks[..chunks.len() - 1]),
+            Some(last_chunk)
+        );
+
+        // with no chunks at all, the whole base is uncovered
+        assert_eq!(
+            get_range_after_chunks(&base_range, &[]),
+            Some(base_range.clone())
+        );
+    }
 }
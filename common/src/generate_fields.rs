@@ -19,12 +19,7 @@ pub fn break_range_into_fields(min: u128, max: u128, size: u128) -> Vec<FieldSiz
         end = start.add(&size).min(max);
 
         // build and push the field
-        let field = FieldSize {
-            range_start: start,
-            range_end: end,
-            range_size: end - start,
-        };
-        fields.push(field);
+        fields.push(FieldSize::new(start, end));
 
         // bump the start
         start = end;
@@ -12,8 +12,10 @@ pub mod db_util;
 pub mod distribution_stats;
 pub mod generate_chunks;
 pub mod generate_fields;
+pub mod msd_prefix_filter;
 pub mod number_stats;
 pub mod residue_filter;
+pub mod stride_filter;
 
 use chrono::{DateTime, Utc};
 use clap::ValueEnum;
@@ -29,10 +31,30 @@ use std::ops::Add;
 
 pub const CLIENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const NEAR_MISS_CUTOFF_PERCENT: f32 = 0.9;
+pub const NEAR_MISS_DISTANCE: u32 = 2;
 pub const DOWNSAMPLE_CUTOFF_PERCENT: f32 = 0.2;
 pub const CLAIM_DURATION_HOURS: u32 = 1;
 pub const DEFAULT_FIELD_SIZE: u128 = 1_000_000_000;
 pub const SAVE_TOP_N_NUMBERS: usize = 10000;
+pub const CONSENSUS_AGREEING_SUBMISSIONS: usize = 2;
+pub const CONSENSUS_MINIMUM_SUBMISSIONS: usize = 2;
+pub const SUSPICIOUS_NUMBERS_PER_SECOND: f64 = 10_000_000_000.0;
+
+/// Get the fewest unique digits a number needs to count as a near miss in detailed searches.
+/// Numbers with more uniques than this are reported individually.
+/// Pass None to use the standard `NEAR_MISS_CUTOFF_PERCENT`, which the server always enforces.
+pub fn get_near_miss_cutoff(base: u32, cutoff_percent: Option<f32>) -> u32 {
+    (base as f32 * cutoff_percent.unwrap_or(NEAR_MISS_CUTOFF_PERCENT)) as u32
+}
+
+/// Whether a field was searched faster than any honest client could manage.
+/// Submissions that return instantly are treated as infinitely fast.
+pub fn is_suspiciously_fast(range_size: u128, elapsed_secs: f32, max_per_second: f64) -> bool {
+    if elapsed_secs <= 0.0 {
+        return range_size > 0;
+    }
+    range_size as f64 / f64::from(elapsed_secs) > max_per_second
+}
 
 /// Each possible search mode the server and client supports.
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -42,6 +64,9 @@ pub enum SearchMode {
     /// Implements optimizations to speed up the search, usually by a factor of around 20.
     /// Does not keep statistics and cannot be quickly verified.
     Niceonly,
+    /// Collect every number within a few unique digits of being completely nice.
+    /// Does not keep statistics, but each number can be verified by the server.
+    NearMiss,
 }
 
 /// Whether we should pick the next or random field when claiming.
@@ -49,6 +74,9 @@ pub enum SearchMode {
 pub enum FieldClaimStrategy {
     Next,
     Random,
+    /// Random, but fields with lower check levels are more likely to be picked.
+    /// Each field is weighted by how far it is below the maximum check level, plus one.
+    WeightedRandom,
 }
 
 /// Data on the bounds of a search range.
@@ -60,6 +88,45 @@ pub struct FieldSize {
     pub range_size: u128,
 }
 
+impl FieldSize {
+    /// Build a range from its bounds, calculating the size.
+    pub fn new(range_start: u128, range_end: u128) -> Self {
+        FieldSize {
+            range_start,
+            range_end,
+            range_size: range_end - range_start,
+        }
+    }
+
+    /// Check whether a number is in the range. The end is exclusive.
+    pub fn contains(&self, n: u128) -> bool {
+        self.range_start <= n && n < self.range_end
+    }
+
+    /// Iterate over every number in the range.
+    pub fn iter(&self) -> impl Iterator<Item = u128> {
+        self.range_start..self.range_end
+    }
+
+    /// Split the range into evenly sized parts, with the remainder in the last part.
+    /// Returns fewer parts if there aren't enough numbers to go around.
+    pub fn split_into(&self, parts: usize) -> Vec<FieldSize> {
+        let parts = (parts as u128).min(self.range_size).max(1);
+        let part_size = self.range_size / parts;
+        (0..parts)
+            .map(|i| {
+                let range_start = self.range_start + i * part_size;
+                let range_end = if i == parts - 1 {
+                    self.range_end
+                } else {
+                    range_start + part_size
+                };
+                FieldSize::new(range_start, range_end)
+            })
+            .collect()
+    }
+}
+
 /// Aggregate data on the niceness of all numbers in the range.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct UniquesDistributionSimple {
@@ -92,6 +159,13 @@ pub struct NiceNumber {
     pub niceness: f32,
 }
 
+impl NiceNumber {
+    /// The fraction of possible digits that appear in the sqube.
+    pub fn niceness_from(num_uniques: u32, base: u32) -> f32 {
+        num_uniques as f32 / base as f32
+    }
+}
+
 /// A base record from the database. Used for analytics.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct BaseRecord {
@@ -123,6 +197,18 @@ pub struct ChunkRecord {
     pub niceness_stdev: Option<f32>,
     pub distribution: Vec<UniquesDistribution>,
     pub numbers: Vec<NiceNumber>,
+    /// When the stats were last recalculated, so unchanged chunks can be skipped.
+    pub last_downsampled_at: Option<DateTime<Utc>>,
+}
+
+/// How much of a range has been checked, gathered in one query.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RangeSummary {
+    pub checked_niceonly: u128,
+    pub checked_detailed: u128,
+    pub minimum_cl: u8,
+    /// When a field's canon submission or check level last changed, if ever.
+    pub last_canon_update: Option<DateTime<Utc>>,
 }
 
 /// A field record from the database.
@@ -139,6 +225,8 @@ pub struct FieldRecord {
     pub canon_submission_id: Option<u32>, // u128?
     pub check_level: u8,
     pub prioritize: bool,
+    /// When the canon submission or check level was last changed, by consensus or a reset.
+    pub canon_updated_at: Option<DateTime<Utc>>,
 }
 
 /// A field sent to the client for processing. Used as input for processing.
@@ -149,6 +237,32 @@ pub struct DataToClient {
     pub range_start: u128,
     pub range_end: u128,
     pub range_size: u128,
+    /// When the field can be handed out to another client. Only set on fields from the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim_expires_at: Option<DateTime<Utc>>,
+    /// The server's clock when the field was claimed. Only set on fields from the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_time: Option<DateTime<Utc>>,
+}
+
+impl DataToClient {
+    /// Whether the claim has run out and the field may have been given to someone else.
+    /// Fields without an expiry, like benchmarks and custom ranges, never expire.
+    pub fn is_claim_expired(&self) -> bool {
+        self.claim_expires_at
+            .is_some_and(|expires_at| Utc::now() > expires_at)
+    }
+}
+
+/// The raw results of processing some range, before they are compiled for the server.
+/// The distribution is empty for searches that don't keep statistics.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct FieldResults {
+    pub distribution: Vec<UniquesDistributionSimple>,
+    pub nice_numbers: Vec<NiceNumberSimple>,
+    /// How many numbers were individually checked, as opposed to skipped by a filter.
+    #[serde(default)]
+    pub processed_count: u128,
 }
 
 /// The compiled results sent to the server after processing.
@@ -171,6 +285,18 @@ pub struct ClaimRecord {
     pub user_ip: String,
 }
 
+/// A validated submission that has not been inserted yet, used for batch inserts.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NewSubmission {
+    pub claim_record: ClaimRecord,
+    pub submit_data: DataToServer,
+    pub user_ip: String,
+    #[serde(default)]
+    pub suspicious: bool,
+    pub distribution: Option<Vec<UniquesDistribution>>,
+    pub numbers: Vec<NiceNumber>,
+}
+
 /// A validated submission ready to send to the database.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct SubmissionRecord {
@@ -184,13 +310,90 @@ pub struct SubmissionRecord {
     pub user_ip: String,
     pub client_version: String,
     pub disqualified: bool,
+    pub suspicious: bool,
     pub distribution: Option<Vec<UniquesDistribution>>,
     pub numbers: Vec<NiceNumber>,
 }
 
+/// How much a single user has contributed, for the leaderboard.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserContribution {
+    pub username: String,
+    pub submission_count: u128,
+    pub checked_size: u128,
+}
+
 /// A submission with no metadata, used for consensus hashing.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct SubmissionCandidate {
     pub distribution: Vec<UniquesDistributionSimple>,
     pub numbers: Vec<NiceNumberSimple>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_niceness_from() {
+        // 69 is the only nice number in base 10
+        assert_eq!(NiceNumber::niceness_from(10, 10), 1.0);
+        // a near miss in base 40
+        assert_eq!(NiceNumber::niceness_from(38, 40), 0.95);
+    }
+
+    #[test]
+    fn test_field_size_contains() {
+        let range = FieldSize::new(47, 100);
+        assert!(range.contains(47));
+        assert!(range.contains(99));
+        assert!(!range.contains(100));
+        assert_eq!(range.iter().count() as u128, range.range_size);
+    }
+
+    #[test]
+    fn test_field_size_split_into() {
+        let range = FieldSize::new(47, 100);
+        let parts = range.split_into(4);
+        assert_eq!(
+            parts,
+            vec![
+                FieldSize::new(47, 60),
+                FieldSize::new(60, 73),
+                FieldSize::new(73, 86),
+                FieldSize::new(86, 100),
+            ]
+        );
+        assert_eq!(FieldSize::new(0, 2).split_into(4).len(), 2);
+    }
+
+    #[test]
+    fn test_is_claim_expired() {
+        let mut claim_data = DataToClient {
+            claim_id: 1,
+            base: 10,
+            range_start: 47,
+            range_end: 100,
+            range_size: 53,
+            claim_expires_at: None,
+            server_time: None,
+        };
+        assert!(!claim_data.is_claim_expired());
+
+        claim_data.claim_expires_at = Some(Utc::now() + TimeDelta::hours(1));
+        assert!(!claim_data.is_claim_expired());
+        claim_data.claim_expires_at = Some(Utc::now() - TimeDelta::hours(1));
+        assert!(claim_data.is_claim_expired());
+    }
+
+    #[test]
+    fn test_is_suspiciously_fast() {
+        // a billion numbers in a minute is plausible, in a millisecond it is not
+        assert!(!is_suspiciously_fast(1_000_000_000, 60.0, 1e9));
+        assert!(is_suspiciously_fast(1_000_000_000, 0.001, 1e9));
+        // an instant submission is only suspicious if there was anything to search
+        assert!(is_suspiciously_fast(1, 0.0, 1e9));
+        assert!(!is_suspiciously_fast(0, 0.0, 1e9));
+    }
+}
@@ -0,0 +1,202 @@
+//! A module for skipping ranges based on their most significant digits.
+//! Across a small enough range, n^2 and n^3 share their leading digits. If those
+//! shared digits already repeat, no number in the range can use every digit once.
+
+use super::*;
+
+/// Ranges at or below this size are not subdivided any further.
+const MIN_RANGE_SIZE: u128 = 1000;
+
+/// How many subranges to split a range into when it can't be skipped.
+const SUBDIVISION_FACTOR: usize = 16;
+
+/// Get the digits shared by the start and end of a monotonic range, most significant first.
+/// If the endpoints have a different number of digits, nothing is shared.
+fn get_shared_prefix(lo: &Natural, hi: &Natural, base: u32) -> Vec<u32> {
+    let lo_digits: Vec<u32> = lo.to_digits_desc(&base);
+    let hi_digits: Vec<u32> = hi.to_digits_desc(&base);
+    if lo_digits.len() != hi_digits.len() {
+        return Vec::new();
+    }
+    lo_digits
+        .into_iter()
+        .zip(hi_digits)
+        .take_while(|(l, h)| l == h)
+        .map(|(l, _)| l)
+        .collect()
+}
+
+/// Get an upper bound on the number of unique digits for any number in the inclusive range.
+pub fn get_max_uniques(range_first: u128, range_last: u128, base: u32) -> u32 {
+    let first = Natural::from(range_first);
+    let last = Natural::from(range_last);
+
+    let first_squared = (&first).pow(2);
+    let last_squared = (&last).pow(2);
+    let first_cubed = &first_squared * &first;
+    let last_cubed = &last_squared * &last;
+
+    // the most digits any number in the range could have
+    let total_digits =
+        last_squared.to_digits_asc(&base).len() + last_cubed.to_digits_asc(&base).len();
+
+    // count every repeat within the fixed leading digits
+    let mut digits_indicator: Vec<bool> = vec![false; base as usize];
+    let mut num_duplicates = 0;
+    for digit in get_shared_prefix(&first_squared, &last_squared, base)
+        .into_iter()
+        .chain(get_shared_prefix(&first_cubed, &last_cubed, base))
+    {
+        if digits_indicator[digit as usize] {
+            num_duplicates += 1;
+        }
+        digits_indicator[digit as usize] = true;
+    }
+
+    let max_uniques = u32::try_from(total_digits - num_duplicates).unwrap_or(u32::MAX);
+    max_uniques.min(base)
+}
+
+/// Recursively split a range, keeping the parts that might have a number above the cutoff.
+/// Stops splitting once the ranges are small enough or the depth runs out.
+fn collect_ranges_above(
+    range_start: u128,
+    range_end: u128,
+    base: u32,
+    cutoff: u32,
+    depth_remaining: u32,
+    valid_ranges: &mut Vec<FieldSize>,
+) {
+    if range_start >= range_end || get_max_uniques(range_start, range_end - 1, base) <= cutoff {
+        return;
+    }
+
+    let range_size = range_end - range_start;
+    if range_size <= MIN_RANGE_SIZE || depth_remaining == 0 {
+        // merge with the previous range if they touch
+        match valid_ranges.last_mut() {
+            Some(last) if last.range_end == range_start => {
+                last.range_end = range_end;
+                last.range_size += range_size;
+            }
+            _ => valid_ranges.push(FieldSize::new(range_start, range_end)),
+        }
+        return;
+    }
+
+    for subrange in FieldSize::new(range_start, range_end).split_into(SUBDIVISION_FACTOR) {
+        collect_ranges_above(
+            subrange.range_start,
+            subrange.range_end,
+            base,
+            cutoff,
+            depth_remaining - 1,
+            valid_ranges,
+        );
+    }
+}
+
+/// Get the subranges that might contain a number with more unique digits than the cutoff.
+/// Everything outside of the returned ranges is guaranteed to be at or below the cutoff.
+pub fn get_ranges_above_cutoff(
+    range_start: u128,
+    range_end: u128,
+    base: u32,
+    cutoff: u32,
+) -> Vec<FieldSize> {
+    let mut valid_ranges = Vec::new();
+    collect_ranges_above(
+        range_start,
+        range_end,
+        base,
+        cutoff,
+        u32::MAX,
+        &mut valid_ranges,
+    );
+    valid_ranges
+}
+
+/// Get the subranges that might contain a nice number.
+pub fn get_valid_ranges(range_start: u128, range_end: u128, base: u32) -> Vec<FieldSize> {
+    get_ranges_above_cutoff(range_start, range_end, base, base - 1)
+}
+
+/// Get the fraction of a range that can be skipped when looking for nice numbers,
+/// subdividing at most `max_depth` times.
+pub fn filter_effectiveness(range: FieldSize, base: u32, max_depth: u32) -> f64 {
+    if range.range_size == 0 {
+        return 0.0;
+    }
+    let mut valid_ranges = Vec::new();
+    collect_ranges_above(
+        range.range_start,
+        range.range_end,
+        base,
+        base - 1,
+        max_depth,
+        &mut valid_ranges,
+    );
+    let valid_size: u128 = valid_ranges.iter().map(|r| r.range_size).sum();
+    1.0 - valid_size as f64 / range.range_size as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_valid_ranges_b10() {
+        assert_eq!(
+            get_valid_ranges(47, 100, 10),
+            Vec::from([FieldSize {
+                range_start: 47,
+                range_end: 100,
+                range_size: 53
+            }])
+        );
+    }
+
+    #[test]
+    fn test_get_valid_ranges_keeps_nice_numbers() {
+        // every nice number near the start of each base must stay inside a valid range
+        for base in 10..=32 {
+            let Ok(Some(base_range)) = base_range::get_base_range_u128(base) else {
+                continue;
+            };
+            let range_end = base_range.range_end.min(base_range.range_start + 100_000);
+            let valid_ranges = get_valid_ranges(base_range.range_start, range_end, base);
+            for num in base_range.range_start..range_end {
+                if client_process::get_num_unique_digits(num, base) == base {
+                    assert!(valid_ranges
+                        .iter()
+                        .any(|r| r.range_start <= num && num < r.range_end));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_valid_ranges_b50() {
+        let range_start = 26507984537059635;
+        let range_end = range_start + 1_000_000;
+        let valid_ranges = get_valid_ranges(range_start, range_end, 50);
+        let valid_size: u128 = valid_ranges.iter().map(|r| r.range_size).sum();
+        assert!(valid_size < range_end - range_start);
+        for r in valid_ranges {
+            assert!(range_start <= r.range_start && r.range_end <= range_end);
+        }
+    }
+
+    #[test]
+    fn test_filter_effectiveness() {
+        // nothing can be skipped in base 10 since 69 is nice
+        assert_eq!(filter_effectiveness(FieldSize::new(47, 100), 10, 8), 0.0);
+
+        // going deeper can only skip more of the range
+        let range = FieldSize::new(26507984537059635, 26507984537059635 + 1_000_000);
+        let shallow = filter_effectiveness(range.clone(), 50, 1);
+        let deep = filter_effectiveness(range, 50, 8);
+        assert!(0.0 <= shallow && shallow <= deep && deep <= 1.0);
+        assert!(deep > 0.0);
+    }
+}
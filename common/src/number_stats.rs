@@ -1,18 +1,78 @@
 //! Expand basic numbers with some redundant stats.
 
 use super::*;
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+fn expand_number(n: &NiceNumberSimple, base: u32) -> NiceNumber {
+    NiceNumber {
+        number: n.number,
+        num_uniques: n.num_uniques,
+        base,
+        niceness: NiceNumber::niceness_from(n.num_uniques, base),
+    }
+}
 
 pub fn expand_numbers(numbers: &[NiceNumberSimple], base: u32) -> Vec<NiceNumber> {
-    let base_f32 = base as f32;
+    numbers.iter().map(|n| expand_number(n, base)).collect()
+}
+
+/// Find the first number that was reported more than once, if any.
+pub fn find_duplicate_number(numbers: &[NiceNumberSimple]) -> Option<u128> {
+    let mut seen = HashSet::new();
     numbers
         .iter()
-        .map(|n| NiceNumber {
-            number: n.number,
-            num_uniques: n.num_uniques,
-            base,
-            niceness: n.num_uniques as f32 / base_f32,
+        .find(|n| !seen.insert(n.number))
+        .map(|n| n.number)
+}
+
+/// Find the first number that falls outside the range it was reported for, if any.
+pub fn find_number_outside_range(numbers: &[NiceNumberSimple], range: &FieldSize) -> Option<u128> {
+    numbers
+        .iter()
+        .find(|n| !range.contains(n.number))
+        .map(|n| n.number)
+}
+
+/// Find a number whose reported unique count doesn't match a fresh calculation, if any.
+/// Returns the number and the calculated count. With the `parallel` feature the numbers are
+/// checked across threads, so which mismatch is found is not guaranteed when there are several.
+pub fn find_unique_count_mismatch(numbers: &[NiceNumber]) -> Option<(&NiceNumber, u32)> {
+    fn check(n: &NiceNumber) -> Option<(&NiceNumber, u32)> {
+        let calculated = client_process::get_num_unique_digits(n.number, n.base);
+        (calculated != n.num_uniques).then_some((n, calculated))
+    }
+
+    #[cfg(feature = "parallel")]
+    return numbers.par_iter().find_map_any(check);
+
+    #[cfg(not(feature = "parallel"))]
+    return numbers.iter().find_map(check);
+}
+
+/// Find the first bin above the cutoff whose count doesn't match the numbers reported for it.
+/// Returns the number of uniques, how many numbers were reported, and how many the bin claimed.
+pub fn find_distribution_count_mismatch(
+    distribution: &[UniquesDistribution],
+    numbers: &[NiceNumber],
+    num_uniques_cutoff: u32,
+) -> Option<(u32, usize, u128)> {
+    // count the numbers once so each bin is a single lookup
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for n in numbers {
+        *counts.entry(n.num_uniques).or_default() += 1;
+    }
+
+    distribution
+        .iter()
+        .filter(|d| d.num_uniques > num_uniques_cutoff)
+        .map(|d| {
+            let count_numbers = counts.get(&d.num_uniques).copied().unwrap_or(0);
+            (d.num_uniques, count_numbers, d.count)
         })
-        .collect()
+        .find(|(_, count_numbers, claimed)| *count_numbers as u128 != *claimed)
 }
 
 pub fn downsample_numbers(submissions: &[SubmissionRecord]) -> Vec<NiceNumber> {
@@ -23,7 +83,7 @@ pub fn downsample_numbers(submissions: &[SubmissionRecord]) -> Vec<NiceNumber> {
     });
 
     // sort and take the top few
-    all_numbers.sort_by(|a, b| b.number.cmp(&a.number));
+    all_numbers.sort_by_key(|n| std::cmp::Reverse(n.number));
     all_numbers
         .iter()
         .take(SAVE_TOP_N_NUMBERS)
@@ -41,4 +101,104 @@ pub fn shrink_numbers(numbers: &[NiceNumber]) -> Vec<NiceNumberSimple> {
         .collect()
 }
 
-// TODO: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_duplicate_number() {
+        let mut numbers = client_process::process_range_detailed(47, 100, 10, None).nice_numbers;
+        assert_eq!(find_duplicate_number(&numbers), None);
+
+        numbers.push(NiceNumberSimple {
+            number: 69,
+            num_uniques: 10,
+        });
+        assert_eq!(find_duplicate_number(&numbers), Some(69));
+    }
+
+    #[test]
+    fn test_find_distribution_count_mismatch() {
+        // the base 40 fixture, keeping numbers down to 30 uniques so there are some to count
+        let base = 40;
+        let results = client_process::process_range_detailed(
+            916284264916,
+            916284264916 + 10000,
+            base,
+            Some(0.75),
+        );
+        let cutoff = get_near_miss_cutoff(base, Some(0.75));
+        let distribution = distribution_stats::expand_distribution(&results.distribution, base);
+        let numbers = expand_numbers(&results.nice_numbers, base);
+        assert!(!numbers.is_empty());
+
+        // the linear scan this replaces
+        let scan = |numbers: &[NiceNumber]| {
+            distribution
+                .iter()
+                .filter(|d| d.num_uniques > cutoff)
+                .map(|d| {
+                    let count_numbers = numbers
+                        .iter()
+                        .filter(|n| n.num_uniques == d.num_uniques)
+                        .count();
+                    (d.num_uniques, count_numbers, d.count)
+                })
+                .find(|(_, count_numbers, claimed)| *count_numbers as u128 != *claimed)
+        };
+
+        // an honest submission is accepted
+        assert_eq!(
+            find_distribution_count_mismatch(&distribution, &numbers, cutoff),
+            None
+        );
+        assert_eq!(scan(&numbers), None);
+
+        // a missing number and an extra number are both caught, in the same bin as before
+        let mut missing = numbers.clone();
+        let dropped = missing.pop().unwrap();
+        let mut extra = numbers.clone();
+        extra.push(dropped.clone());
+        for tampered in [missing, extra] {
+            let mismatch = find_distribution_count_mismatch(&distribution, &tampered, cutoff);
+            assert_eq!(mismatch.map(|m| m.0), Some(dropped.num_uniques));
+            assert_eq!(mismatch, scan(&tampered));
+        }
+    }
+
+    #[test]
+    fn test_find_unique_count_mismatch() {
+        let base = 40;
+        let results = client_process::process_range_detailed(
+            916284264916,
+            916284264916 + 10000,
+            base,
+            Some(0.75),
+        );
+        let mut numbers = expand_numbers(&results.nice_numbers, base);
+        assert!(!numbers.is_empty());
+        assert_eq!(find_unique_count_mismatch(&numbers), None);
+
+        // inflate one count, the only mismatch must be the one found
+        let tampered = numbers.len() / 2;
+        let honest_count = numbers[tampered].num_uniques;
+        numbers[tampered].num_uniques += 1;
+        let (found, calculated) = find_unique_count_mismatch(&numbers).unwrap();
+        assert_eq!(found.number, numbers[tampered].number);
+        assert_eq!(calculated, honest_count);
+    }
+
+    #[test]
+    fn test_find_number_outside_range() {
+        let numbers = client_process::process_range_detailed(47, 100, 10, None).nice_numbers;
+        assert_eq!(
+            find_number_outside_range(&numbers, &FieldSize::new(47, 100)),
+            None
+        );
+        // 69 is nice, but not part of this field
+        assert_eq!(
+            find_number_outside_range(&numbers, &FieldSize::new(70, 100)),
+            Some(69)
+        );
+    }
+}
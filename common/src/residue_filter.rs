@@ -1,18 +1,84 @@
 //! A module for deaing with residue filters
 //! For more information: https://beautifulthorns.wixsite.com/home/post/progress-update-on-the-search-for-nice-numbers
 
-/// Get a list of residue filters for a base.
-pub fn get_residue_filter(base: &u32) -> Vec<u32> {
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Residue filters that have already been calculated, keyed by base.
+static RESIDUE_FILTER_CACHE: OnceLock<Mutex<HashMap<u32, Arc<Vec<u32>>>>> = OnceLock::new();
+
+/// Calculate the residue filter for a base from scratch.
+fn calculate_residue_filter(base: u32) -> Vec<u32> {
     let target_residue = base * (base - 1) / 2 % (base - 1);
     (0..(base - 1))
         .filter(|num| (num.pow(2) + num.pow(3)) % (base - 1) == target_residue)
         .collect()
 }
 
+/// Get a list of residue filters for a base.
+/// Each base is only calculated once, after that it comes from the cache.
+pub fn get_residue_filter(base: &u32) -> Vec<u32> {
+    let cache = RESIDUE_FILTER_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    cache
+        .entry(*base)
+        .or_insert_with(|| Arc::new(calculate_residue_filter(*base)))
+        .to_vec()
+}
+
+/// A residue filter as a lookup table, so checking a number doesn't scan the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidueFilter {
+    modulus: u128,
+    allowed: Vec<bool>,
+}
+
+impl ResidueFilter {
+    /// Build the lookup table for a base from its (cached) residue filter.
+    pub fn new(base: u32) -> Self {
+        let mut allowed = vec![false; (base - 1) as usize];
+        for residue in get_residue_filter(&base) {
+            allowed[residue as usize] = true;
+        }
+        ResidueFilter {
+            modulus: u128::from(base - 1),
+            allowed,
+        }
+    }
+
+    /// Check whether a number could be nice based on its residue mod (base - 1).
+    pub fn allows(&self, n: u128) -> bool {
+        self.allowed[(n % self.modulus) as usize]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_get_residue_filter_cached() {
+        assert_eq!(get_residue_filter(&40), get_residue_filter(&40));
+        assert_eq!(get_residue_filter(&40), calculate_residue_filter(40));
+        assert_ne!(get_residue_filter(&40), get_residue_filter(&50));
+    }
+
+    #[test]
+    fn test_residue_filter_allows() {
+        for base in [10, 11, 40, 50, 97] {
+            let list = get_residue_filter(&base);
+            let filter = ResidueFilter::new(base);
+            for n in 0..1000u128 {
+                let residue = (n % u128::from(base - 1)) as u32;
+                assert_eq!(
+                    filter.allows(n),
+                    list.contains(&residue),
+                    "base {base}, n {n}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_get_residue_filter() {
         assert_eq!(get_residue_filter(&10), Vec::from([0, 3, 6, 8]));
@@ -0,0 +1,241 @@
+//! A module for jumping directly between candidates that pass the residue filters.
+//! Combines the residue filter mod (b-1) with a filter on the least significant digits
+//! mod b^k. Since these are coprime, the valid residues mod (b-1) * b^k repeat forever,
+//! so we can precompute the gaps between them once and step through any range.
+
+use super::*;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many least significant digits to check when building a stride table.
+pub const STRIDE_LSD_DIGITS: u32 = 2;
+
+/// The largest modulus a stride table is allowed to have when tuning k.
+/// Beyond this the table takes too long to build and too much memory to hold.
+const MAX_TUNING_MODULUS: u128 = 10_000_000;
+
+/// Stride tables that have already been built, keyed by base and k.
+type StrideTableCache = Mutex<HashMap<(u32, u32), Arc<StrideTable>>>;
+static STRIDE_TABLE_CACHE: OnceLock<StrideTableCache> = OnceLock::new();
+
+/// Get the number of least significant digits to check for a base without measuring anything.
+pub fn get_recommended_k(_base: u32) -> u32 {
+    STRIDE_LSD_DIGITS
+}
+
+/// Measure how long it takes to build a table and check every candidate in the sample for
+/// each k from 1 to 3, and return the fastest. Skips any k whose table would be too large.
+pub fn tune_k(base: u32, sample_range: FieldSize) -> u32 {
+    let mut best: Option<(u32, Duration)> = None;
+    for k in 1..=3 {
+        if (base as u128 - 1) * (base as u128).pow(k) > MAX_TUNING_MODULUS {
+            break;
+        }
+        let before = Instant::now();
+        let table = StrideTable::new(base, k);
+        let _ = table
+            .iter(sample_range.range_start, sample_range.range_end)
+            .filter(|num| client_process::get_is_nice(*num, base))
+            .count();
+        let elapsed = before.elapsed();
+        if best.is_none_or(|(_, best_elapsed)| elapsed < best_elapsed) {
+            best = Some((k, elapsed));
+        }
+    }
+    best.map_or_else(|| get_recommended_k(base), |(k, _)| k)
+}
+
+/// The valid residues mod (b-1) * b^k and the gaps between each one and the next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrideTable {
+    pub modulus: u128,
+    pub residues: Vec<u128>,
+    pub gaps: Vec<u128>,
+}
+
+/// Check whether the last k digits of n^2 and n^3 are all different.
+/// Assumes the square has at least k digits, which is true for every number in a base range.
+fn has_unique_lsd(num: u128, base: u128, k: u32) -> bool {
+    let lsd_modulus = base.pow(k);
+    let squared = num * num % lsd_modulus;
+    let cubed = squared * num % lsd_modulus;
+
+    // create a boolean array that represents all possible digits
+    let mut digits_indicator = vec![false; base as usize];
+    for mut n in [squared, cubed] {
+        for _ in 0..k {
+            let digit = (n % base) as usize;
+            if digits_indicator[digit] {
+                return false;
+            }
+            digits_indicator[digit] = true;
+            n /= base;
+        }
+    }
+    true
+}
+
+impl StrideTable {
+    /// Build the table for a base, checking the last k digits.
+    pub fn new(base: u32, k: u32) -> Self {
+        let base_u128 = base as u128;
+        let residue_modulus = base_u128 - 1;
+        let modulus = residue_modulus * base_u128.pow(k);
+        let residue_filter = residue_filter::ResidueFilter::new(base);
+
+        let residues: Vec<u128> = (0..modulus)
+            .filter(|r| residue_filter.allows(*r))
+            .filter(|r| has_unique_lsd(*r, base_u128, k))
+            .collect();
+
+        let gaps = residues
+            .iter()
+            .enumerate()
+            .map(|(i, r)| match residues.get(i + 1) {
+                Some(next) => next - r,
+                None => modulus - r + residues[0],
+            })
+            .collect();
+
+        StrideTable {
+            modulus,
+            residues,
+            gaps,
+        }
+    }
+
+    /// Get the table for a base and k, only building it the first time it is requested.
+    pub fn cached(base: u32, k: u32) -> Arc<Self> {
+        let cache = STRIDE_TABLE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry((base, k))
+            .or_insert_with(|| Arc::new(StrideTable::new(base, k)))
+            .clone()
+    }
+
+    /// Drop every cached table, so the next request for each one builds it again.
+    pub fn clear_cache() {
+        if let Some(cache) = STRIDE_TABLE_CACHE.get() {
+            cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+
+    /// Iterate over every candidate in the range.
+    pub fn iter(&self, range_start: u128, range_end: u128) -> StrideIter<'_> {
+        let offset = range_start % self.modulus;
+        let mut block_start = range_start - offset;
+        let mut index = self.residues.partition_point(|r| *r < offset);
+        if index == self.residues.len() {
+            block_start += self.modulus;
+            index = 0;
+        }
+        StrideIter {
+            table: self,
+            next: block_start + self.residues.get(index).copied().unwrap_or(0),
+            index,
+            range_end,
+        }
+    }
+}
+
+/// An iterator over the candidates in a range, created by `StrideTable::iter`.
+pub struct StrideIter<'a> {
+    table: &'a StrideTable,
+    next: u128,
+    index: usize,
+    range_end: u128,
+}
+
+impl Iterator for StrideIter<'_> {
+    type Item = u128;
+
+    fn next(&mut self) -> Option<u128> {
+        if self.table.residues.is_empty() || self.next >= self.range_end {
+            return None;
+        }
+        let current = self.next;
+        self.next += self.table.gaps[self.index];
+        self.index = (self.index + 1) % self.table.gaps.len();
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stride_table_b10() {
+        let table = StrideTable::new(10, 1);
+        assert_eq!(table.modulus, 90);
+        assert!(table.residues.contains(&69));
+        assert_eq!(table.gaps.iter().sum::<u128>(), table.modulus);
+    }
+
+    #[test]
+    fn test_has_unique_lsd_high_base() {
+        // digits 128 and above must not alias the low digits
+        assert!(has_unique_lsd(129, 130, 1));
+        assert!(!has_unique_lsd(203, 130, 2));
+        assert!(!has_unique_lsd(699, 130, 2));
+
+        // digits past 255 are still tracked
+        assert!(has_unique_lsd(299, 300, 1));
+        assert!(!has_unique_lsd(126, 300, 2));
+    }
+
+    #[test]
+    fn test_tune_k() {
+        for base in [10, 40] {
+            let base_range = base_range::get_base_range_u128(base).unwrap().unwrap();
+            let sample_range = FieldSize::new(
+                base_range.range_start,
+                base_range.range_end.min(base_range.range_start + 100_000),
+            );
+            assert!((1..=3).contains(&tune_k(base, sample_range)));
+        }
+        // k = 3 is too large to build for a high base
+        assert!(tune_k(97, FieldSize::new(0, 0)) <= 2);
+    }
+
+    #[test]
+    fn test_stride_table_cached() {
+        StrideTable::clear_cache();
+        let cached = StrideTable::cached(40, STRIDE_LSD_DIGITS);
+        assert!(Arc::ptr_eq(
+            &cached,
+            &StrideTable::cached(40, STRIDE_LSD_DIGITS)
+        ));
+        assert_eq!(
+            cached.residues,
+            StrideTable::new(40, STRIDE_LSD_DIGITS).residues
+        );
+        StrideTable::clear_cache();
+        assert!(!Arc::ptr_eq(
+            &cached,
+            &StrideTable::cached(40, STRIDE_LSD_DIGITS)
+        ));
+    }
+
+    #[test]
+    fn test_stride_table_empty() {
+        let table = StrideTable::new(11, STRIDE_LSD_DIGITS);
+        assert!(table.residues.is_empty());
+        assert_eq!(table.iter(0, 1000).count(), 0);
+    }
+
+    #[test]
+    fn test_stride_iter_matches_filter() {
+        for base in [10, 12, 40, 50] {
+            let table = StrideTable::new(base, STRIDE_LSD_DIGITS);
+            let range_start = 1_234_567;
+            let range_end = range_start + 3 * table.modulus + 17;
+            let expected: Vec<u128> = (range_start..range_end)
+                .filter(|n| table.residues.binary_search(&(n % table.modulus)).is_ok())
+                .collect();
+            let strided: Vec<u128> = table.iter(range_start, range_end).collect();
+            assert_eq!(strided, expected);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Export a base's chunk stats and distributions to CSV files for spreadsheets.
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use nice_common::db_util;
+use nice_common::ChunkRecord;
+
+use clap::Parser;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// The base to export
+    base: u32,
+
+    /// The directory to write the CSV files to
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+}
+
+/// Format an optional stat, leaving the cell empty if it hasn't been calculated.
+fn opt_cell(value: Option<f32>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// One row per chunk with its aggregate stats.
+/// Numbers are written as plain decimal strings so no precision is lost.
+fn chunks_csv(chunks: &[ChunkRecord]) -> String {
+    let mut contents = String::from(
+        "chunk_id,base,range_start,range_end,range_size,checked_detailed,checked_niceonly,minimum_cl,niceness_mean,niceness_stdev\n",
+    );
+    for c in chunks {
+        let _ = writeln!(
+            contents,
+            "{},{},{},{},{},{},{},{},{},{}",
+            c.chunk_id,
+            c.base,
+            c.range_start,
+            c.range_end,
+            c.range_size,
+            c.checked_detailed,
+            c.checked_niceonly,
+            c.minimum_cl,
+            opt_cell(c.niceness_mean),
+            opt_cell(c.niceness_stdev)
+        );
+    }
+    contents
+}
+
+/// One row per chunk and unique count in each chunk's distribution.
+fn distribution_csv(chunks: &[ChunkRecord]) -> String {
+    let mut contents = String::from("chunk_id,base,num_uniques,count,niceness,density\n");
+    for c in chunks {
+        for d in &c.distribution {
+            let _ = writeln!(
+                contents,
+                "{},{},{},{},{},{}",
+                c.chunk_id, c.base, d.num_uniques, d.count, d.niceness, d.density
+            );
+        }
+    }
+    contents
+}
+
+fn main() {
+    // parse args from command line
+    let cli = Cli::parse();
+
+    // get the base and its chunks
+    let mut conn = db_util::get_database_connection();
+    let base = db_util::get_base_by_id(&mut conn, cli.base).unwrap_or_else(|e| {
+        eprintln!("Base {} not found: {e}", cli.base);
+        process::exit(2);
+    });
+    let mut chunks = db_util::get_chunks_in_base(&mut conn, cli.base).unwrap_or_else(|e| {
+        eprintln!("Failed to get chunks for base {}: {e}", cli.base);
+        process::exit(2);
+    });
+    chunks.sort_by_key(|c| c.range_start);
+
+    // write both files
+    let chunks_path = cli
+        .output_dir
+        .join(format!("base_{}_chunks.csv", base.base));
+    let distribution_path = cli
+        .output_dir
+        .join(format!("base_{}_distribution.csv", base.base));
+    for (path, contents) in [
+        (&chunks_path, chunks_csv(&chunks)),
+        (&distribution_path, distribution_csv(&chunks)),
+    ] {
+        if let Err(e) = fs::write(path, contents) {
+            eprintln!("Failed to write {}: {e}", path.display());
+            process::exit(1);
+        }
+    }
+
+    println!(
+        "Exported {} chunks of base {} to {} and {}.",
+        chunks.len(),
+        base.base,
+        chunks_path.display(),
+        distribution_path.display()
+    );
+}
@@ -0,0 +1,157 @@
+//! Recompute a single field from scratch and compare it against the canon submission.
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use nice_common::client_process::process_range_detailed;
+use nice_common::db_util;
+use nice_common::distribution_stats::shrink_distribution;
+use nice_common::number_stats::shrink_numbers;
+use nice_common::{FieldResults, NiceNumberSimple, SubmissionRecord, UniquesDistributionSimple};
+
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::process;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// The field to recompute
+    field_id: u128,
+}
+
+/// Compare the recomputed distribution against the submitted one.
+fn check_distribution(
+    submitted: &[UniquesDistributionSimple],
+    recomputed: &[UniquesDistributionSimple],
+    range_size: u128,
+    mismatches: &mut Vec<String>,
+) {
+    let submitted_total: u128 = submitted.iter().map(|d| d.count).sum();
+    if submitted_total != range_size {
+        mismatches.push(format!(
+            "Total distribution count is incorrect (submitted {submitted_total}, range was {range_size})."
+        ));
+    }
+
+    let submitted: BTreeMap<u32, u128> =
+        submitted.iter().map(|d| (d.num_uniques, d.count)).collect();
+    for d in recomputed {
+        let submitted_count = submitted.get(&d.num_uniques).copied().unwrap_or(0);
+        if submitted_count != d.count {
+            mismatches.push(format!(
+                "Count of numbers with {} uniques is incorrect (submitted {submitted_count}, recomputed {}).",
+                d.num_uniques, d.count
+            ));
+        }
+    }
+}
+
+/// Compare the recomputed nice numbers against the submitted ones.
+fn check_numbers(
+    submitted: &[NiceNumberSimple],
+    recomputed: &[NiceNumberSimple],
+    mismatches: &mut Vec<String>,
+) {
+    let submitted: BTreeMap<u128, u32> = submitted
+        .iter()
+        .map(|n| (n.number, n.num_uniques))
+        .collect();
+    let recomputed: BTreeMap<u128, u32> = recomputed
+        .iter()
+        .map(|n| (n.number, n.num_uniques))
+        .collect();
+
+    for (number, num_uniques) in &recomputed {
+        match submitted.get(number) {
+            None => mismatches.push(format!(
+                "Nice number {number} with {num_uniques} uniques is missing from the submission."
+            )),
+            Some(submitted_uniques) if submitted_uniques != num_uniques => {
+                mismatches.push(format!(
+                    "Unique count for {number} is incorrect (submitted as {submitted_uniques}, recomputed {num_uniques})."
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    for (number, num_uniques) in &submitted {
+        if !recomputed.contains_key(number) {
+            mismatches.push(format!(
+                "Nice number {number} with {num_uniques} uniques was submitted but is not nice enough to include."
+            ));
+        }
+    }
+}
+
+/// List every way the canon submission differs from the recomputed results.
+fn find_mismatches(
+    canon: &SubmissionRecord,
+    recomputed: &FieldResults,
+    range_size: u128,
+) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    match &canon.distribution {
+        Some(distribution) => check_distribution(
+            &shrink_distribution(distribution),
+            &recomputed.distribution,
+            range_size,
+            &mut mismatches,
+        ),
+        None => mismatches
+            .push("Unique distribution must be present for detailed searches.".to_string()),
+    }
+    check_numbers(
+        &shrink_numbers(&canon.numbers),
+        &recomputed.nice_numbers,
+        &mut mismatches,
+    );
+    mismatches
+}
+
+fn main() {
+    // parse args from command line
+    let cli = Cli::parse();
+
+    // get the field and its canon submission
+    let mut conn = db_util::get_database_connection();
+    let field = db_util::get_field_by_id(&mut conn, cli.field_id).unwrap_or_else(|e| {
+        eprintln!("Field #{} not found: {e}", cli.field_id);
+        process::exit(2);
+    });
+    let Some(canon_submission_id) = field.canon_submission_id else {
+        eprintln!(
+            "Field #{} has no canon submission to compare.",
+            field.field_id
+        );
+        process::exit(2);
+    };
+    let canon = db_util::get_submission_by_id(&mut conn, u128::from(canon_submission_id))
+        .unwrap_or_else(|e| {
+            eprintln!("Submission #{canon_submission_id} not found: {e}");
+            process::exit(2);
+        });
+
+    // recompute the field from scratch
+    println!(
+        "Recomputing field #{} (base {}, {} numbers)...",
+        field.field_id, field.base, field.range_size
+    );
+    let recomputed = process_range_detailed(field.range_start, field.range_end, field.base, None);
+
+    let mismatches = find_mismatches(&canon, &recomputed, field.range_size);
+    if mismatches.is_empty() {
+        println!(
+            "Field #{} matches canon submission #{}.",
+            field.field_id, canon.submission_id
+        );
+    } else {
+        println!(
+            "Field #{} disagrees with canon submission #{}:",
+            field.field_id, canon.submission_id
+        );
+        for mismatch in mismatches {
+            println!("    {mismatch}");
+        }
+        process::exit(1);
+    }
+}
@@ -3,247 +3,522 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::too_many_lines)]
 
-use nice_common::consensus;
+use nice_common::consensus::{self, ConsensusConfig, DisagreementReport};
 use nice_common::db_util;
 use nice_common::distribution_stats;
+use nice_common::generate_chunks;
 use nice_common::number_stats;
 use nice_common::DOWNSAMPLE_CUTOFF_PERCENT;
-use nice_common::{FieldRecord, SubmissionRecord};
-use std::io::{self, Write};
+use nice_common::{
+    BaseRecord, ChunkRecord, FieldRecord, FieldSize, RangeSummary, SubmissionRecord,
+};
 
-fn main() {
-    // get db connection
-    let mut conn = db_util::get_database_connection();
-    println!("Database connection established. Scheduled jobs started.");
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use rayon::prelude::*;
+use std::env;
+use std::fmt::Write;
 
-    // get all bases
-    let bases = db_util::get_all_bases(&mut conn).unwrap();
-    for base_record in bases {
-        let base = base_record.base;
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// How many bases to process at once (defaults to the number of cores)
+    #[arg(long)]
+    jobs: Option<usize>,
 
-        println!("=== BASE {base} CONSENSUS ===");
+    /// Only process this base
+    #[arg(long)]
+    base: Option<u32>,
 
-        // get all fields
-        // TODO: get fields to check and their submissions in one operation
-        let fields_to_check: Vec<FieldRecord> =
-            db_util::get_fields_in_base_with_detailed_subs(&mut conn, base).unwrap();
+    /// Only run this phase
+    #[arg(long, value_enum)]
+    only: Option<Phase>,
 
-        for field in fields_to_check {
-            // Get all qualified and detailed submissions for the field
-            let submissions =
-                db_util::get_submissions_qualified_detailed_for_field(&mut conn, field.field_id)
-                    .unwrap();
+    /// Recalculate every chunk, even ones with no new submissions
+    #[arg(long)]
+    full: bool,
+}
+
+/// The phases of the scheduled jobs, run in this order for each base.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Phase {
+    Consensus,
+    Downsample,
+}
+
+/// Read the consensus rules from the environment, falling back to the defaults.
+fn get_consensus_config() -> ConsensusConfig {
+    let default = ConsensusConfig::default();
+    let read_env = |key: &str, default: usize| {
+        env::var(key)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    ConsensusConfig {
+        agreeing_submissions: read_env(
+            "NICE_CONSENSUS_AGREEING_SUBMISSIONS",
+            default.agreeing_submissions,
+        ),
+        minimum_submissions: read_env(
+            "NICE_CONSENSUS_MINIMUM_SUBMISSIONS",
+            default.minimum_submissions,
+        ),
+        disqualified_versions: env::var("NICE_CONSENSUS_DISQUALIFIED_VERSIONS")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or(default.disqualified_versions),
+    }
+}
+
+/// Read the fraction of a range that must be checked before its detailed stats are calculated.
+/// Falls back to the default if `NICE_DOWNSAMPLE_CUTOFF` is unset or invalid.
+fn get_downsample_cutoff() -> f32 {
+    env::var("NICE_DOWNSAMPLE_CUTOFF")
+        .ok()
+        .and_then(|v| v.parse::<f32>().ok())
+        .filter(|v| !v.is_nan())
+        .map_or(DOWNSAMPLE_CUTOFF_PERCENT, |v| v.clamp(0.0, 1.0))
+}
+
+/// Write out a disagreement report so it stands out in the logs.
+fn write_disagreement(out: &mut String, report: &DisagreementReport) {
+    let _ = writeln!(
+        out,
+        "WARNING: Field #{} has conflicting submissions! Majority: {:?}",
+        report.field_id, report.majority_submission_ids
+    );
+    for conflict in &report.conflicts {
+        let mut differs = Vec::new();
+        if conflict.distribution_differs {
+            differs.push("distribution");
+        }
+        if conflict.numbers_differ {
+            differs.push("nice_numbers");
+        }
+        let _ = writeln!(
+            out,
+            "    Submissions {:?} differ in {}",
+            conflict.submission_ids,
+            differs.join(" and ")
+        );
+    }
+}
+
+/// Establish consensus for every field in a base with detailed submissions.
+/// Returns true if any field or submission was changed.
+fn establish_consensus(
+    conn: &mut db_util::PgPooledConnection,
+    base_record: &BaseRecord,
+    consensus_config: &ConsensusConfig,
+    out: &mut String,
+) -> bool {
+    let base = base_record.base;
+    let mut changed = false;
+
+    let _ = writeln!(out, "=== BASE {base} CONSENSUS ===");
+
+    // get all fields
+    // TODO: get fields to check and their submissions in one operation
+    let fields_to_check: Vec<FieldRecord> =
+        db_util::get_fields_in_base_with_detailed_subs(conn, base).unwrap();
+
+    for field in fields_to_check {
+        // Get all qualified and detailed submissions for the field
+        let submissions =
+            db_util::get_submissions_qualified_detailed_for_field(conn, field.field_id).unwrap();
+
+        // Establish the consensus
+        let consensus::ConsensusOutcome {
+            canon_submission,
+            check_level,
+            disagreement,
+            disqualified_ids: disqualified,
+        } = consensus::evaluate_consensus(&field, &submissions, consensus_config).unwrap();
+
+        // Throw out anything from a known-bad client so it isn't counted again
+        if !disqualified.is_empty() {
+            let _ = writeln!(
+                out,
+                "Field #{}: Disqualified submissions {disqualified:?} from known-bad client versions.",
+                field.field_id
+            );
+            db_util::disqualify_submissions(conn, &disqualified).unwrap();
+            changed = true;
+        }
 
-            // Establish the consensus
-            let (canon_submission, check_level) =
-                consensus::evaluate_consensus(&field, &submissions).unwrap();
+        // Flag any submissions that disagree with the majority
+        if let Some(report) = &disagreement {
+            write_disagreement(out, report);
+        }
 
-            match &canon_submission {
-                None => {
-                    if field.canon_submission_id.is_some() || field.check_level > 1 {
-                        println!(
+        match &canon_submission {
+            None => {
+                if field.canon_submission_id.is_some() || field.check_level > 1 {
+                    let _ = writeln!(
+                        out,
                         "WARNING: Field #{} claimed to be checked (Submission #{:?}, CL{}) but no submissions were found, so it was reset to CL{}.",
                         field.field_id, field.canon_submission_id, field.check_level, check_level
                     );
-                        db_util::update_field_canon_and_cl(
-                            &mut conn,
-                            field.field_id,
-                            None,
-                            check_level,
-                        )
+                    db_util::update_field_canon_and_cl(conn, field.field_id, None, check_level)
                         .unwrap();
-                    }
+                    changed = true;
                 }
-                #[allow(clippy::cast_possible_truncation)] // TODO: fix submission_id type mismatch
-                Some(sub) => {
-                    print!(
-                        "Field #{}: CL{}, Canon Submission #{}, ",
-                        field.field_id, check_level, sub.submission_id
-                    );
-                    // Update the field if necessary
-                    if field.canon_submission_id != Some(sub.submission_id as u32)
-                        || field.check_level != check_level
-                    {
-                        db_util::update_field_canon_and_cl(
-                            &mut conn,
-                            field.field_id,
-                            Some(sub.submission_id as u32),
-                            check_level,
-                        )
-                        .unwrap();
-                        println!("Updated!");
-                    } else {
-                        println!("No change.");
-                    }
+            }
+            #[allow(clippy::cast_possible_truncation)] // TODO: fix submission_id type mismatch
+            Some(sub) => {
+                let _ = write!(
+                    out,
+                    "Field #{}: CL{}, Canon Submission #{}, ",
+                    field.field_id, check_level, sub.submission_id
+                );
+                // Update the field if necessary
+                if field.canon_submission_id != Some(sub.submission_id as u32)
+                    || field.check_level != check_level
+                {
+                    db_util::update_field_canon_and_cl(
+                        conn,
+                        field.field_id,
+                        Some(sub.submission_id as u32),
+                        check_level,
+                    )
+                    .unwrap();
+                    changed = true;
+                    let _ = writeln!(out, "Updated!");
+                } else {
+                    let _ = writeln!(out, "No change.");
                 }
             }
         }
+    }
 
-        println!();
-        println!("=== BASE {base} DOWNSAMPLING ===");
+    let _ = writeln!(out);
+    changed
+}
 
-        // get basic stats like how much has been cheked
-        let base_checked_niceonly = db_util::get_count_checked_by_range(
-            &mut conn,
-            1,
-            base_record.range_start,
-            base_record.range_end,
-        )
-        .unwrap();
-        let base_checked_detailed = db_util::get_count_checked_by_range(
-            &mut conn,
-            2,
-            base_record.range_start,
-            base_record.range_end,
-        )
-        .unwrap();
+/// Whether a chunk's stats need to be recalculated: it has never been downsampled,
+/// something was submitted since, a canon submission changed (by consensus or a field reset),
+/// or its checked totals changed.
+fn chunk_is_stale(
+    chunk: &ChunkRecord,
+    summary: &RangeSummary,
+    latest_submit_time: Option<DateTime<Utc>>,
+) -> bool {
+    let Some(last_downsampled_at) = chunk.last_downsampled_at else {
+        return true;
+    };
+    latest_submit_time.is_some_and(|t| t > last_downsampled_at)
+        || summary
+            .last_canon_update
+            .is_some_and(|t| t > last_downsampled_at)
+        || chunk.checked_niceonly != summary.checked_niceonly
+        || chunk.checked_detailed != summary.checked_detailed
+        || chunk.minimum_cl != summary.minimum_cl
+}
 
-        #[allow(clippy::cast_precision_loss)]
-        let base_percent_checked_detailed =
-            base_checked_detailed as f32 / base_record.range_size as f32;
-        let base_minimum_cl = db_util::get_minimum_cl_by_range(
-            &mut conn,
-            base_record.range_start,
-            base_record.range_end,
-        )
-        .unwrap();
+/// Downsample the stats for each chunk in a base and for the base itself.
+/// Chunks with nothing new since they were last downsampled are skipped unless `full` is set,
+/// and the base is only recalculated if one of its chunks was.
+fn downsample_base(
+    conn: &mut db_util::PgPooledConnection,
+    base_record: &BaseRecord,
+    downsample_cutoff: f32,
+    full: bool,
+    out: &mut String,
+) {
+    let base = base_record.base;
 
-        // create vec for all fields in the base
-        let mut base_submissions: Vec<SubmissionRecord> = Vec::new();
+    // anything submitted after this will be picked up next time
+    let started_at = Utc::now();
 
-        // loop thorugh chunks in the base
-        let chunks = db_util::get_chunks_in_base(&mut conn, base).unwrap();
-        for chunk in chunks {
-            let chunk_size = chunk.range_size;
-            print!("Chunk #{}: ", chunk.chunk_id);
-            io::stdout().flush().unwrap();
+    let _ = writeln!(out, "=== BASE {base} DOWNSAMPLING ===");
 
-            // get basic stats like how much has been cheked
-            let minimum_cl =
-                db_util::get_minimum_cl_by_range(&mut conn, chunk.range_start, chunk.range_end)
-                    .unwrap();
-            // if the min check level is X or more, we've already searched everything
-            let checked_niceonly = if minimum_cl >= 1 {
-                chunk.range_size
-            } else {
-                db_util::get_count_checked_by_range(
-                    &mut conn,
-                    1,
-                    chunk.range_start,
-                    chunk.range_end,
-                )
-                .unwrap()
-            };
-            let checked_detailed = if minimum_cl >= 2 {
-                chunk.range_size
-            } else if checked_niceonly == 0 {
-                // checked_niceonly will always be less than checked_detailed
-                // if it's zero then this will be too
-                0
-            } else {
-                db_util::get_count_checked_by_range(
-                    &mut conn,
-                    2,
-                    chunk.range_start,
-                    chunk.range_end,
-                )
-                .unwrap()
-            };
-            #[allow(clippy::cast_precision_loss)]
-            let chunk_percent_checked_detailed = checked_detailed as f32 / chunk_size as f32;
-            print!(
-                "CL{}, Checked {:.1}%, ",
-                minimum_cl,
-                chunk_percent_checked_detailed * 100f32
-            );
-            io::stdout().flush().unwrap();
+    // get basic stats like how much has been cheked
+    let RangeSummary {
+        checked_niceonly: base_checked_niceonly,
+        checked_detailed: base_checked_detailed,
+        minimum_cl: base_minimum_cl,
+        ..
+    } = db_util::get_range_summary(conn, base_record.range_start, base_record.range_end).unwrap();
 
-            // get all submissions for the chunk
-            let mut submissions: Vec<SubmissionRecord> = if checked_detailed == 0 {
-                Vec::new()
-            } else {
-                db_util::get_canon_submissions_by_range(
-                    &mut conn,
-                    chunk.range_start,
-                    chunk.range_end,
-                )
-                .unwrap()
-            };
+    #[allow(clippy::cast_precision_loss)]
+    let base_percent_checked_detailed =
+        base_checked_detailed as f32 / base_record.range_size as f32;
 
-            // update chunk record
-            let mut updated_chunk = chunk.clone();
-            updated_chunk.checked_niceonly = checked_niceonly;
-            updated_chunk.checked_detailed = checked_detailed;
-            updated_chunk.minimum_cl = minimum_cl;
-            if chunk_percent_checked_detailed > DOWNSAMPLE_CUTOFF_PERCENT {
-                // only update these detailed stats if we have a representative sample
-                updated_chunk.distribution =
-                    distribution_stats::downsample_distributions(&submissions, base);
-                updated_chunk.numbers = number_stats::downsample_numbers(&submissions);
-                let (niceness_mean, niceness_stdev) =
-                    distribution_stats::mean_stdev_from_distribution(&updated_chunk.distribution);
-                updated_chunk.niceness_mean = Some(niceness_mean);
-                updated_chunk.niceness_stdev = Some(niceness_stdev);
-                print!("Mean {niceness_mean:.2}, StDev {niceness_stdev:.2}, ");
-                io::stdout().flush().unwrap();
-            } else {
-                // otherwise reset to "no data" default
-                updated_chunk.distribution = Vec::new();
-                updated_chunk.numbers = Vec::new();
-                updated_chunk.niceness_mean = None;
-                updated_chunk.niceness_stdev = None;
-            }
+    // find which chunks have changed since they were last downsampled
+    let chunks: Vec<(ChunkRecord, RangeSummary, bool)> = db_util::get_chunks_in_base(conn, base)
+        .unwrap()
+        .into_iter()
+        .map(|chunk| {
+            let summary =
+                db_util::get_range_summary(conn, chunk.range_start, chunk.range_end).unwrap();
+            let latest_submit_time =
+                db_util::get_latest_submit_time_by_range(conn, chunk.range_start, chunk.range_end)
+                    .unwrap();
+            let stale = full || chunk_is_stale(&chunk, &summary, latest_submit_time);
+            (chunk, summary, stale)
+        })
+        .collect();
+    let chunk_sizes: Vec<FieldSize> = chunks
+        .iter()
+        .map(|(c, _, _)| FieldSize {
+            range_start: c.range_start,
+            range_end: c.range_end,
+            range_size: c.range_size,
+        })
+        .collect();
+    let base_size = FieldSize {
+        range_start: base_record.range_start,
+        range_end: base_record.range_end,
+        range_size: base_record.range_size,
+    };
+    let remaining_range = generate_chunks::get_range_after_chunks(&base_size, &chunk_sizes);
 
-            // save it
-            if chunk == updated_chunk {
-                println!("No change.");
-            } else {
-                db_util::update_chunk_stats(&mut conn, updated_chunk).unwrap();
-                println!("Updated!");
-            }
-            // save submissions for the base stats
-            base_submissions.append(&mut submissions);
-        }
+    // the base is built from every chunk's submissions, so it changes whenever any chunk does
+    let base_stale = full
+        || remaining_range.is_some()
+        || base_record.checked_niceonly != base_checked_niceonly
+        || base_record.checked_detailed != base_checked_detailed
+        || base_record.minimum_cl != base_minimum_cl
+        || chunks.iter().any(|(_, _, stale)| *stale);
+    if !base_stale {
+        let _ = writeln!(out, "Base {base}: No new submissions, skipped.");
+        let _ = writeln!(out);
+        return;
+    }
 
-        // TODO: get remaining submissions between final chunk and end of base range
+    // create vec for all fields in the base
+    let mut base_submissions: Vec<SubmissionRecord> = Vec::new();
 
-        print!("Base {base}: ",);
-        print!(
+    // loop thorugh chunks in the base
+    for (chunk, summary, stale) in chunks {
+        let chunk_size = chunk.range_size;
+        let _ = write!(out, "Chunk #{}: ", chunk.chunk_id);
+
+        let RangeSummary {
+            checked_niceonly,
+            checked_detailed,
+            minimum_cl,
+            ..
+        } = summary;
+        #[allow(clippy::cast_precision_loss)]
+        let chunk_percent_checked_detailed = checked_detailed as f32 / chunk_size as f32;
+        let _ = write!(
+            out,
             "CL{}, Checked {:.1}%, ",
-            base_minimum_cl,
-            base_percent_checked_detailed * 100f32
+            minimum_cl,
+            chunk_percent_checked_detailed * 100f32
         );
 
-        // update base record
-        let mut updated_base = base_record.clone();
-        updated_base.checked_niceonly = base_checked_niceonly;
-        updated_base.checked_detailed = base_checked_detailed;
-        updated_base.minimum_cl = base_minimum_cl;
-        if base_percent_checked_detailed > DOWNSAMPLE_CUTOFF_PERCENT {
+        // get all submissions for the chunk
+        let mut submissions: Vec<SubmissionRecord> = if checked_detailed == 0 {
+            Vec::new()
+        } else {
+            db_util::get_canon_submissions_by_range(conn, chunk.range_start, chunk.range_end)
+                .unwrap()
+        };
+
+        // the submissions are still needed for the base, but the chunk itself is unchanged
+        if !stale {
+            let _ = writeln!(out, "No new submissions, skipped.");
+            base_submissions.append(&mut submissions);
+            continue;
+        }
+
+        // update chunk record
+        let mut updated_chunk = chunk.clone();
+        updated_chunk.checked_niceonly = checked_niceonly;
+        updated_chunk.checked_detailed = checked_detailed;
+        updated_chunk.minimum_cl = minimum_cl;
+        if chunk_percent_checked_detailed > downsample_cutoff {
             // only update these detailed stats if we have a representative sample
-            updated_base.distribution =
-                distribution_stats::downsample_distributions(&base_submissions, base);
-            updated_base.numbers = number_stats::downsample_numbers(&base_submissions);
+            updated_chunk.distribution =
+                distribution_stats::downsample_distributions(&submissions, base);
+            updated_chunk.numbers = number_stats::downsample_numbers(&submissions);
             let (niceness_mean, niceness_stdev) =
-                distribution_stats::mean_stdev_from_distribution(&updated_base.distribution);
-            updated_base.niceness_mean = Some(niceness_mean);
-            updated_base.niceness_stdev = Some(niceness_stdev);
-            print!("Mean {niceness_mean:.2}, StDev {niceness_stdev:.2}, ");
+                distribution_stats::mean_stdev_from_distribution(&updated_chunk.distribution);
+            updated_chunk.niceness_mean = Some(niceness_mean);
+            updated_chunk.niceness_stdev = Some(niceness_stdev);
+            let _ = write!(out, "Mean {niceness_mean:.2}, StDev {niceness_stdev:.2}, ");
         } else {
             // otherwise reset to "no data" default
-            updated_base.distribution = Vec::new();
-            updated_base.numbers = Vec::new();
-            updated_base.niceness_mean = None;
-            updated_base.niceness_stdev = None;
+            updated_chunk.distribution = Vec::new();
+            updated_chunk.numbers = Vec::new();
+            updated_chunk.niceness_mean = None;
+            updated_chunk.niceness_stdev = None;
         }
 
-        // save it
-        if base_record == updated_base {
-            println!("No change.");
+        // save it, marking when it was checked even if nothing changed
+        let changed = chunk != updated_chunk;
+        updated_chunk.last_downsampled_at = Some(started_at);
+        db_util::update_chunk_stats(conn, updated_chunk).unwrap();
+        if changed {
+            let _ = writeln!(out, "Updated!");
         } else {
-            db_util::update_base_stats(&mut conn, updated_base).unwrap();
-            println!("Updated!");
+            let _ = writeln!(out, "No change.");
         }
-        println!();
+        // save submissions for the base stats
+        base_submissions.append(&mut submissions);
+    }
+
+    // get remaining submissions between final chunk and end of base range
+    if let Some(remaining) = remaining_range {
+        let mut remaining_submissions = db_util::get_canon_submissions_by_range(
+            conn,
+            remaining.range_start,
+            remaining.range_end,
+        )
+        .unwrap();
+        let _ = writeln!(
+            out,
+            "Range after final chunk: {} submissions from {} to {}",
+            remaining_submissions.len(),
+            remaining.range_start,
+            remaining.range_end
+        );
+        base_submissions.append(&mut remaining_submissions);
     }
+
+    let _ = write!(out, "Base {base}: ");
+    let _ = write!(
+        out,
+        "CL{}, Checked {:.1}%, ",
+        base_minimum_cl,
+        base_percent_checked_detailed * 100f32
+    );
+
+    // update base record
+    let mut updated_base = base_record.clone();
+    updated_base.checked_niceonly = base_checked_niceonly;
+    updated_base.checked_detailed = base_checked_detailed;
+    updated_base.minimum_cl = base_minimum_cl;
+    if base_percent_checked_detailed > downsample_cutoff {
+        // only update these detailed stats if we have a representative sample
+        updated_base.distribution =
+            distribution_stats::downsample_distributions(&base_submissions, base);
+        updated_base.numbers = number_stats::downsample_numbers(&base_submissions);
+        let (niceness_mean, niceness_stdev) =
+            distribution_stats::mean_stdev_from_distribution(&updated_base.distribution);
+        updated_base.niceness_mean = Some(niceness_mean);
+        updated_base.niceness_stdev = Some(niceness_stdev);
+        let _ = write!(out, "Mean {niceness_mean:.2}, StDev {niceness_stdev:.2}, ");
+    } else {
+        // otherwise reset to "no data" default
+        updated_base.distribution = Vec::new();
+        updated_base.numbers = Vec::new();
+        updated_base.niceness_mean = None;
+        updated_base.niceness_stdev = None;
+    }
+
+    // save it
+    if *base_record == updated_base {
+        let _ = writeln!(out, "No change.");
+    } else {
+        db_util::update_base_stats(conn, updated_base).unwrap();
+        let _ = writeln!(out, "Updated!");
+    }
+    let _ = writeln!(out);
+}
+
+/// Run the selected phases for a single base.
+/// Returns the log output so each base's lines stay together when run in parallel.
+fn process_base(
+    conn: &mut db_util::PgPooledConnection,
+    base_record: &BaseRecord,
+    consensus_config: &ConsensusConfig,
+    downsample_cutoff: f32,
+    only: Option<Phase>,
+    full: bool,
+) -> String {
+    let mut out = String::new();
+    let mut consensus_changed = false;
+    if only.is_none_or(|phase| phase == Phase::Consensus) {
+        consensus_changed = establish_consensus(conn, base_record, consensus_config, &mut out);
+    }
+    if only.is_none_or(|phase| phase == Phase::Downsample) {
+        // a new canon submission doesn't show up as a new submission, so recalculate everything
+        downsample_base(
+            conn,
+            base_record,
+            downsample_cutoff,
+            full || consensus_changed,
+            &mut out,
+        );
+    }
+    out
+}
+
+fn main() {
+    // parse args from command line
+    let cli = Cli::parse();
+
+    // get db connection pool
+    let pool = db_util::get_database_pool();
+    let mut conn = pool.get().expect("Error getting a database connection");
+    println!("Database connection established. Scheduled jobs started.");
+
+    // get the consensus rules
+    let consensus_config = get_consensus_config();
+    println!(
+        "Consensus requires {} agreeing of at least {} submissions.",
+        consensus_config.agreeing_submissions, consensus_config.minimum_submissions
+    );
+    if !consensus_config.disqualified_versions.is_empty() {
+        println!(
+            "Disqualifying submissions from client versions {:?}.",
+            consensus_config.disqualified_versions
+        );
+    }
+
+    // get the threshold for calculating detailed stats
+    let downsample_cutoff = get_downsample_cutoff();
+    println!(
+        "Detailed stats require {:.1}% of a range to be checked.",
+        downsample_cutoff * 100f32
+    );
+
+    // get the requested base, or all of them
+    let bases = match cli.base {
+        Some(base) => {
+            let Some(base_record) = db_util::try_get_base_by_id(&mut conn, base).unwrap() else {
+                eprintln!("Base {base} does not exist.");
+                std::process::exit(1);
+            };
+            vec![base_record]
+        }
+        None => db_util::get_all_bases(&mut conn).unwrap(),
+    };
+    drop(conn);
+
+    // each worker holds a connection for a whole base, so never run more than the pool size
+    let num_jobs = cli
+        .jobs
+        .unwrap_or_else(rayon::current_num_threads)
+        .clamp(1, pool.max_size() as usize);
+    println!("Processing {} bases with {num_jobs} workers.", bases.len());
+    println!();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(num_jobs)
+        .build()
+        .expect("Error building the thread pool")
+        .install(|| {
+            bases.into_par_iter().for_each(|base_record| {
+                let mut conn = pool.get().expect("Error getting a database connection");
+                let out = process_base(
+                    &mut conn,
+                    &base_record,
+                    &consensus_config,
+                    downsample_cutoff,
+                    cli.only,
+                    cli.full,
+                );
+                print!("{out}");
+            });
+        });
 }
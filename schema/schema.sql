@@ -32,7 +32,8 @@ CREATE TABLE chunks (
     niceness_mean REAL,
     niceness_stdev REAL,
     distribution JSONB NOT NULL DEFAULT '[]',
-    numbers JSONB NOT NULL DEFAULT '[]'
+    numbers JSONB NOT NULL DEFAULT '[]',
+    last_downsampled_at TIMESTAMPTZ
 );
 -- FIELDS: INDIVIDUAL SEARCH RANGES
 CREATE TABLE fields (
@@ -45,7 +46,8 @@ CREATE TABLE fields (
     last_claim_time TIMESTAMPTZ,
     canon_submission_id INTEGER,
     check_level INTEGER NOT NULL DEFAULT 0,
-    prioritize BOOLEAN NOT NULL DEFAULT 'false'
+    prioritize BOOLEAN NOT NULL DEFAULT 'false',
+    canon_updated_at TIMESTAMPTZ
 );
 -- CLAIMS: LOG OF CLAIM REQUESTS
 CREATE TABLE claims (
@@ -67,6 +69,7 @@ CREATE TABLE submissions (
     user_ip VARCHAR NOT NULL,
     client_version VARCHAR NOT NULL,
     disqualified BOOLEAN NOT NULL DEFAULT 'false',
+    suspicious BOOLEAN NOT NULL DEFAULT 'false',
     distribution JSONB,
     numbers JSONB NOT NULL DEFAULT '[]'
 );
@@ -81,3 +84,5 @@ CREATE INDEX idx_fields_base_id ON fields(base_id);
 CREATE INDEX idx_fields_range_start ON fields(range_start);
 CREATE INDEX idx_fields_range_end ON fields(range_end);
 CREATE INDEX idx_fields_check_level ON fields(check_level);
+CREATE INDEX idx_fields_claim_order ON fields(prioritize DESC, id ASC);
+CREATE UNIQUE INDEX idx_submissions_claim_id ON submissions(claim_id);
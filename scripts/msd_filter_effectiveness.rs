@@ -0,0 +1,26 @@
+#!/usr/bin/env rust-script
+//! ```cargo
+//! [dependencies]
+//! nice_common = { path = "../common" }
+//! ```
+
+use nice_common::FieldSize;
+
+fn main() {
+    let sample_size = 1000000000;
+    let max_depth = 8;
+    println!("Sample size: {}, max depth: {}", sample_size, max_depth);
+
+    for base in 10..51 {
+        let base_range = nice_common::base_range::get_base_range_u128(base).unwrap();
+        if let Some(range) = base_range {
+            let sample = FieldSize::new(
+                range.range_start,
+                range.range_end.min(range.range_start + sample_size),
+            );
+            let effectiveness =
+                nice_common::msd_prefix_filter::filter_effectiveness(sample, base, max_depth);
+            println!("Base {}: {:.2}% skipped", base, effectiveness * 100.0);
+        }
+    }
+}